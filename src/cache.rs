@@ -0,0 +1,107 @@
+//! # Detection Cache Module
+//!
+//! Persists VS/SDK/UCRT detection results between runs.
+//!
+//! ## Purpose
+//! Skips the vswhere invocation and registry walks when the same query was
+//! answered before. Cached entries are re-validated (toolset dir and the cl.exe
+//! of the compiler host picked at detection still exist) before use, so a VS
+//! update or uninstall falls back to a fresh scan.
+//!
+//! ## Key Functions
+//! - `cache_path()` - `%LOCALAPPDATA%\vcv\cache.json`
+//! - `load(key, host, target)` - Fetch and validate a cached entry
+//! - `store(key, entry)` - Save an entry (best effort)
+//!
+//! ## Dependencies
+//! - `serde_json` for the cache file
+
+use vcv_rs::detect::{SdkInfo, VsInfo};
+use vcv_rs::Arch;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Cached detection results for one query
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub vs: VsInfo,
+    pub sdk: Option<SdkInfo>,
+    pub ucrt: Option<SdkInfo>,
+    /// Host whose `bin\Host<arch>` compiler was picked (differs from the requested host
+    /// when an emulated one is used); None = the requested host
+    #[serde(default)]
+    pub compiler_host: Option<Arch>,
+}
+
+/// Cache file location
+pub fn cache_path() -> Option<PathBuf> {
+    let base = std::env::var_os("LOCALAPPDATA")?;
+    Some(PathBuf::from(base).join("vcv").join("cache.json"))
+}
+
+fn read_all() -> BTreeMap<String, CacheEntry> {
+    cache_path()
+        .and_then(|p| std::fs::read_to_string(p).ok())
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+/// Cached entry is still usable: toolset, cl.exe and SDK/UCRT version dirs exist
+fn is_valid(e: &CacheEntry, host: Arch, target: Arch) -> bool {
+    let host = e.compiler_host.unwrap_or(host);
+    let cl = e.vs.tools.join("bin").join(host.host_dir()).join(target.bin_dir()).join("cl.exe");
+    e.vs.tools.exists()
+        && cl.exists()
+        && e.sdk.as_ref().is_none_or(|s| s.path.join("Lib").join(&s.version).exists())
+        && e.ucrt.as_ref().is_none_or(|u| u.path.join("Lib").join(&u.version).exists())
+}
+
+/// Load a validated cache entry
+pub fn load(key: &str, host: Arch, target: Arch) -> Option<CacheEntry> {
+    let entry = read_all().remove(key)?;
+    is_valid(&entry, host, target).then_some(entry)
+}
+
+/// Save a cache entry, keeping other keys (errors are ignored: the cache is optional)
+pub fn store(key: &str, entry: &CacheEntry) {
+    let Some(path) = cache_path() else {
+        return;
+    };
+    let mut all = read_all();
+    all.insert(key.to_string(), entry.clone());
+
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    if let Ok(json) = serde_json::to_string_pretty(&all) {
+        let _ = std::fs::write(&path, json);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_with_emulated_compiler_host() {
+        let root = std::env::temp_dir().join(format!("vcv-cache-{}", std::process::id()));
+        let tools = root.join("VC").join("Tools").join("MSVC").join("14.40.33807");
+        let bin = tools.join("bin").join("Hostx64").join("x86");
+        std::fs::create_dir_all(&bin).unwrap();
+        std::fs::write(bin.join("cl.exe"), "").unwrap();
+
+        let vs = VsInfo {
+            install: root.clone(),
+            version: "17.9".into(),
+            vc: root.join("VC"),
+            tools_ver: "14.40.33807".into(),
+            tools,
+        };
+        let mut entry = CacheEntry { vs, sdk: None, ucrt: None, compiler_host: None };
+        assert!(!is_valid(&entry, Arch::Arm64, Arch::X86));
+        entry.compiler_host = Some(Arch::X64);
+        assert!(is_valid(&entry, Arch::Arm64, Arch::X86));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
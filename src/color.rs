@@ -0,0 +1,93 @@
+//! # Color Module
+//!
+//! ANSI colors for the info and warning lines on stderr.
+//!
+//! ## Purpose
+//! Makes the detected versions and warnings easy to spot in long CI logs.
+//! `--color auto` (default) colors only when stderr is a terminal, `-q` is not
+//! given and `NO_COLOR` is unset; `always`/`never` override both.
+//!
+//! ## Key Functions
+//! - `init(choice, quiet)` - Decide once per run whether stderr is colored
+//! - `version()` / `sdk()` - Highlight a VS/toolset or SDK/UCRT version
+//! - `warning()` - The `Warning:` label
+//!
+//! ## Dependencies
+//! - None (raw escape sequences; `SetConsoleMode` turns them on in older consoles)
+
+use clap::ValueEnum;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// When to color stderr
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+#[link(name = "kernel32")]
+unsafe extern "system" {
+    fn GetStdHandle(std_handle: u32) -> isize;
+    fn GetConsoleMode(handle: isize, mode: *mut u32) -> i32;
+    fn SetConsoleMode(handle: isize, mode: u32) -> i32;
+}
+
+/// Enable escape sequence processing on the stderr console (a no-op where it's already on)
+fn enable_vt() -> bool {
+    const STD_ERROR_HANDLE: u32 = -12i32 as u32;
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+    // SAFETY: plain Win32 calls on the process's own stderr handle
+    unsafe {
+        let handle = GetStdHandle(STD_ERROR_HANDLE);
+        let mut mode = 0;
+        GetConsoleMode(handle, &mut mode) != 0
+            && (mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0
+                || SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0)
+    }
+}
+
+/// Decide whether stderr gets colors for the rest of the run
+pub fn init(choice: ColorChoice, quiet: bool) {
+    let on = match choice {
+        ColorChoice::Always => {
+            enable_vt();
+            true
+        }
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            !quiet
+                && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                && std::io::stderr().is_terminal()
+                && enable_vt()
+        }
+    };
+    ENABLED.store(on, Ordering::Relaxed);
+}
+
+fn paint(code: &str, text: &str) -> String {
+    if ENABLED.load(Ordering::Relaxed) {
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// VS or toolset version (bold cyan)
+pub fn version(text: &str) -> String {
+    paint("1;36", text)
+}
+
+/// SDK or UCRT version (green)
+pub fn sdk(text: &str) -> String {
+    paint("32", text)
+}
+
+/// `Warning:` label (bold yellow)
+pub fn warning() -> String {
+    paint("1;33", "Warning:")
+}
@@ -0,0 +1,136 @@
+//! # Config File Module
+//!
+//! Default options from `vcv.toml` files.
+//!
+//! ## Purpose
+//! Saves retyping the same flags (`-a arm64 -v 2022 --spectre`) on every run.
+//! Precedence: command line > `.\vcv.toml` (project) > `%APPDATA%\vcv\config.toml`
+//! (user) > built-in defaults.
+//!
+//! ```toml
+//! arch = "arm64"
+//! vs = 2022
+//! format = "cmd"
+//! spectre = true
+//! ```
+//!
+//! ## Key Functions
+//! - `load_all()` - Read the user and project files (missing files are skipped)
+//! - `Config::apply()` - Fill in every option not given on the command line
+//!
+//! ## Dependencies
+//! - `toml` for parsing
+//! - `clap` for telling command-line values apart from defaults
+
+use crate::{Args, Format};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, ValueEnum};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use vcv_rs::format::PathStyle;
+use vcv_rs::Arch;
+
+/// Options settable from a config file (keys are the long option names)
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    arch: Option<String>,
+    host: Option<String>,
+    format: Option<String>,
+    path_style: Option<String>,
+    vs: Option<u16>,
+    prerelease: Option<bool>,
+    spectre: Option<bool>,
+    clang: Option<bool>,
+    cmake_tools: Option<bool>,
+    store: Option<bool>,
+    no_atlmfc: Option<bool>,
+    clr: Option<bool>,
+    debuggers: Option<bool>,
+    winrt: Option<bool>,
+    compiler_only: Option<bool>,
+    no_sdk: Option<bool>,
+    no_ucrt: Option<bool>,
+    cache: Option<bool>,
+    quiet: Option<bool>,
+    no_inherit: Option<bool>,
+    append: Option<bool>,
+    unix_paths: Option<bool>,
+    json_compact: Option<bool>,
+}
+
+/// User config location
+fn user_path() -> Option<PathBuf> {
+    let base = std::env::var_os("APPDATA")?;
+    Some(PathBuf::from(base).join("vcv").join("config.toml"))
+}
+
+fn load(path: &Path) -> Result<Option<Config>, String> {
+    let Ok(text) = std::fs::read_to_string(path) else {
+        return Ok(None);
+    };
+    toml::from_str(&text).map(Some).map_err(|e| format!("Invalid config {}: {}", path.display(), e))
+}
+
+/// Config files that exist, lowest precedence first (user, then project)
+pub fn load_all() -> Result<Vec<(PathBuf, Config)>, String> {
+    let mut out = Vec::new();
+    for path in user_path().into_iter().chain([PathBuf::from("vcv.toml")]) {
+        if let Some(cfg) = load(&path)? {
+            out.push((path, cfg));
+        }
+    }
+    Ok(out)
+}
+
+fn parse_arch(value: &str) -> Result<Arch, String> {
+    Arch::from_str(value, true).map_err(|_| format!("invalid arch '{}'", value))
+}
+
+impl Config {
+    /// Copy every value set in this file into `args`, unless given on the command line
+    pub fn apply(&self, args: &mut Args, matches: &ArgMatches) -> Result<(), String> {
+        let from_cli = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+
+        if let Some(a) = &self.arch
+            && !from_cli("arch")
+        {
+            args.arch = parse_arch(a)?;
+        }
+        if let Some(h) = &self.host
+            && !from_cli("host")
+        {
+            args.host = parse_arch(h)?;
+        }
+        if let Some(f) = &self.format
+            && !from_cli("format")
+        {
+            args.format = Format::from_str(f, true).map_err(|_| format!("invalid format '{}'", f))?;
+        }
+        if let Some(p) = &self.path_style
+            && !from_cli("path_style")
+        {
+            args.path_style = PathStyle::from_str(p, true).map_err(|_| format!("invalid path-style '{}'", p))?;
+        }
+        if let Some(v) = self.vs
+            && !from_cli("vs_year")
+        {
+            args.vs_year = Some(v);
+        }
+
+        macro_rules! flags {
+            ($($name:ident),*) => {$(
+                if let Some(v) = self.$name
+                    && !from_cli(stringify!($name))
+                {
+                    args.$name = v;
+                }
+            )*};
+        }
+        flags!(
+            prerelease, spectre, clang, cmake_tools, store, no_atlmfc, clr, debuggers, winrt, compiler_only, no_sdk, no_ucrt,
+            cache, quiet, no_inherit, append, unix_paths, json_compact
+        );
+        Ok(())
+    }
+}
@@ -4,25 +4,62 @@
 //!
 //! ## Purpose
 //! Locates VS toolchain and SDK paths without running slow batch scripts.
-//! Uses vswhere.exe for VS detection and Windows registry for SDK/UCRT.
+//! Uses vswhere.exe for VS detection (full VS and standalone Build Tools)
+//! and Windows registry for SDK/UCRT.
 //!
 //! ## Key Functions
-//! - `detect_vs(vs_year)` - Find VS installation, optionally filter by year (2017/2019/2022)
-//! - `detect_sdk()` - Find Windows 10/11 SDK via registry
-//! - `detect_ucrt()` - Find Universal CRT via registry
+//! - `detect_vs(vswhere, prerelease, product, vs_year, toolset)` - Find VS installation, optionally filter by year (2017/2019/2022)
+//! - `detect_sdk(select, version)` - Find Windows 10/11 SDK via registry (pinned, newest or oldest)
+//! - `list_sdks()` - List installed SDK versions (for error messages)
+//! - `detect_ucrt(version)` - Find Universal CRT via registry (pinned or newest)
+//! - `list_ucrts()` - List installed UCRT versions (for error messages)
+//! - `vs_from_path()` / `sdk_from_root()` / `ucrt_from_root()` - Same, from explicit directories
+//! - `vswhere_error()` - Why vswhere gave no usable answer (for error messages)
+//! - `set_vswhere_timeout()` / `output_with_timeout()` - Bound how long vswhere may run
 //! - `list_vs_versions()` - List all installed VS versions (for error messages)
+//! - `list_vs_installs()` / `toolsets_in()` / `ucrt_versions()` - Full inventory (`--list`)
+//! - `list_toolsets(vs)` - List side-by-side MSVC toolsets of a VS install
+//! - `redist_version(vs)` - Default VC++ redistributable version (`VC\Redist\MSVC\<ver>`)
+//! - `detect_framework()` - Find the 64-bit .NET Framework 4.x (C++/CLI, `--clr`)
+//! - `arch_support(vs, host, sdk, ucrt)` - Check which target arches have compiler + libs
+//! - `missing_target_libs(sdk, ucrt, target)` - SDK/UCRT lib dirs absent for one target arch
+//! - `compiler_host(vs, host, target)` - Host toolset whose compiler for `target` is installed
 //!
 //! ## Dependencies
 //! - `registry` module for Windows registry access
-//! - `serde_json` for parsing vswhere.exe JSON output
+//! - `serde_json` for parsing vswhere.exe JSON output (UTF-8 or UTF-16)
 
 use crate::registry::reg_find;
-use serde::Deserialize;
-use std::path::PathBuf;
-use std::process::Command;
+use crate::{Arch, Product, SdkSelect};
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Default vswhere.exe location (override with `--vswhere` / `VCV_VSWHERE`)
+pub const DEFAULT_VSWHERE: &str = r"C:\Program Files (x86)\Microsoft Visual Studio\Installer\vswhere.exe";
+
+/// vswhere time limit in milliseconds (0 = unlimited)
+static VSWHERE_TIMEOUT_MS: AtomicU64 = AtomicU64::new(5000);
+
+/// Set once vswhere has timed out, so later queries in this process fail fast
+static VSWHERE_TIMED_OUT: AtomicBool = AtomicBool::new(false);
+
+/// Limit how long vswhere may run before it is killed (`Duration::ZERO` = no limit)
+pub fn set_vswhere_timeout(timeout: Duration) {
+    VSWHERE_TIMEOUT_MS.store(timeout.as_millis() as u64, Ordering::Relaxed);
+}
+
+/// vswhere query: all instances as UTF-8 JSON (`vswhere_args` adds `-products`)
+const VSWHERE_ARGS: [&str; 4] = ["-all", "-format", "json", "-utf8"];
+
+/// Toolset-specific marker suffixes (`Microsoft.VCToolsVersion.<v>.default.txt`), newest first
+const TOOLSET_MARKERS: [&str; 4] = ["v144", "v143", "v142", "v141"];
 
 /// Visual Studio installation info
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VsInfo {
     pub install: PathBuf,
     pub version: String,
@@ -31,19 +68,70 @@ pub struct VsInfo {
     pub tools: PathBuf,
 }
 
-/// SDK/UCRT info
-#[derive(Debug)]
+/// SDK/UCRT (and .NET Framework) info
+/// `version` is empty for a flat, versionless SDK layout (`include\um`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SdkInfo {
     pub path: PathBuf,
     pub version: String,
 }
 
+impl SdkInfo {
+    /// SDK headers installed for this version (false for lib-only partial installs)
+    pub fn has_headers(&self) -> bool {
+        sdk_has_headers(&self.path.join("include").join(&self.version))
+    }
+}
+
+/// Target arch availability for one host toolset
+#[derive(Debug)]
+pub struct ArchSupport {
+    pub target: &'static str,
+    pub compiler: bool,
+    pub libs: bool,
+}
+
 #[derive(Deserialize)]
 struct VsWhereEntry {
     #[serde(rename = "installationPath")]
     installation_path: String,
     #[serde(rename = "installationVersion", default)]
     installation_version: String,
+    #[serde(rename = "productId", default)]
+    product_id: String,
+}
+
+impl VsWhereEntry {
+    /// Product name without the `Microsoft.VisualStudio.Product.` prefix (e.g. "BuildTools")
+    fn product(&self) -> String {
+        self.product_id.trim_start_matches("Microsoft.VisualStudio.Product.").to_string()
+    }
+}
+
+/// Decode UTF-16 bytes (without BOM) into a String
+fn decode_utf16(bytes: &[u8], big_endian: bool) -> Option<String> {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|c| if big_endian { u16::from_be_bytes([c[0], c[1]]) } else { u16::from_le_bytes([c[0], c[1]]) })
+        .collect();
+    String::from_utf16(&units).ok()
+}
+
+/// Parse vswhere JSON output
+/// Old vswhere builds ignore `-utf8` and emit UTF-16, so handle a UTF-16 BOM
+/// and fall back to UTF-16LE, then BE, when the UTF-8 parse fails; a UTF-8 BOM is skipped
+fn parse_vswhere(bytes: &[u8]) -> Option<Vec<VsWhereEntry>> {
+    let bytes = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(bytes);
+    if let Some(rest) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+        return serde_json::from_str(&decode_utf16(rest, false)?).ok();
+    }
+    if let Some(rest) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        return serde_json::from_str(&decode_utf16(rest, true)?).ok();
+    }
+    serde_json::from_slice(bytes)
+        .ok()
+        .or_else(|| serde_json::from_str(&decode_utf16(bytes, false)?).ok())
+        .or_else(|| serde_json::from_str(&decode_utf16(bytes, true)?).ok())
 }
 
 /// Read single-line text file
@@ -53,17 +141,48 @@ fn read_txt(path: &PathBuf) -> Option<String> {
         .map(|s| s.trim().to_string())
 }
 
+/// Version from the newest `Microsoft.<kind>.v14x.default.txt` marker in `aux`,
+/// else from the generic `Microsoft.<kind>.default.txt`
+fn read_marker(aux: &Path, kind: &str) -> Option<String> {
+    TOOLSET_MARKERS
+        .iter()
+        .find_map(|v| read_txt(&aux.join(format!("Microsoft.{}.{}.default.txt", kind, v))))
+        .or_else(|| read_txt(&aux.join(format!("Microsoft.{}.default.txt", kind))))
+}
+
+/// Toolset directory has a cl.exe for any host/target pair (x86-only and arm64-hosted installs too)
+fn has_compiler(tools: &Path) -> bool {
+    let bin = tools.join("bin");
+    [Arch::X64, Arch::X86, Arch::Arm64].iter().any(|host| {
+        [Arch::X64, Arch::X86, Arch::Arm64, Arch::Arm]
+            .iter()
+            .any(|target| bin.join(host.host_dir()).join(target.bin_dir()).join("cl.exe").exists())
+    })
+}
+
 /// Build VsInfo from vswhere entry
-fn build_vs_info(vs: VsWhereEntry) -> Option<VsInfo> {
+/// `toolset` selects a side-by-side MSVC version instead of the default one
+fn build_vs_info(vs: VsWhereEntry, toolset: Option<&str>) -> Option<VsInfo> {
     let install = PathBuf::from(&vs.installation_path);
     let vc = install.join("VC");
     let aux = vc.join("Auxiliary").join("Build");
 
-    // Get tools version (try v143 first, then default)
-    let tools_ver = read_txt(&aux.join("Microsoft.VCToolsVersion.v143.default.txt"))
-        .or_else(|| read_txt(&aux.join("Microsoft.VCToolsVersion.default.txt")))?;
+    let msvc = vc.join("Tools").join("MSVC");
 
-    let tools = vc.join("Tools").join("MSVC").join(&tools_ver);
+    // Get tools version (explicit, else the newest v14x marker, then default, then the newest
+    // toolset directory with a compiler when the marker files are missing or stale)
+    let tools_ver = match toolset {
+        Some(v) => v.to_string(),
+        None => read_marker(&aux, "VCToolsVersion")
+            .filter(|v| msvc.join(v).exists())
+            .or_else(|| {
+                toolsets_in(&install)
+                    .into_iter()
+                    .find(|v| has_compiler(&msvc.join(v)))
+            })?,
+    };
+
+    let tools = msvc.join(&tools_ver);
     if !tools.exists() {
         return None;
     }
@@ -77,21 +196,125 @@ fn build_vs_info(vs: VsWhereEntry) -> Option<VsInfo> {
     })
 }
 
-/// Detect VS installation via vswhere
-/// If vs_year is Some, filter by year (2019, 2022, etc.)
-pub fn detect_vs(vs_year: Option<u16>) -> Option<VsInfo> {
-    let vswhere = PathBuf::from(r"C:\Program Files (x86)\Microsoft Visual Studio\Installer\vswhere.exe");
+/// Run vswhere and parse its JSON output
+/// `prerelease` adds `-prerelease` so Preview installs are listed alongside stable ones
+/// `product` restricts `-products` to one edition (default: all, including Build Tools)
+/// A hang is reported on stderr right away: it is the one failure the user can't see otherwise
+fn run_vswhere(vswhere: &Path, prerelease: bool, product: Option<Product>) -> Option<Vec<VsWhereEntry>> {
+    let first_timeout = !VSWHERE_TIMED_OUT.load(Ordering::Relaxed);
+    query_vswhere(vswhere, prerelease, product)
+        .inspect_err(|e| {
+            if first_timeout && VSWHERE_TIMED_OUT.load(Ordering::Relaxed) {
+                eprintln!("Warning: vswhere {} (raise the limit with --timeout)", e);
+            }
+        })
+        .ok()
+}
+
+/// Why vswhere gave no usable answer (missing, failed to start, unparseable output
+/// plus its stderr); None when its output parsed fine. For error messages
+pub fn vswhere_error(vswhere: &Path, prerelease: bool, product: Option<Product>) -> Option<String> {
+    query_vswhere(vswhere, prerelease, product).err()
+}
+
+/// vswhere arguments: every product (`*`, so Build Tools-only installs are found) unless one is requested
+fn vswhere_args(prerelease: bool, product: Option<Product>) -> Vec<&'static str> {
+    let mut args = VSWHERE_ARGS.to_vec();
+    args.extend(["-products", product.map_or("*", Product::id)]);
+    if prerelease {
+        args.push("-prerelease");
+    }
+    args
+}
+
+fn query_vswhere(vswhere: &Path, prerelease: bool, product: Option<Product>) -> Result<Vec<VsWhereEntry>, String> {
     if !vswhere.exists() {
-        return None;
+        return Err(format!("{} not found", vswhere.display()));
     }
+    if VSWHERE_TIMED_OUT.load(Ordering::Relaxed) {
+        return Err(format!("{}: timed out earlier in this run", vswhere.display()));
+    }
+
+    let mut cmd = Command::new(vswhere);
+    cmd.args(vswhere_args(prerelease, product));
+    let timeout = Duration::from_millis(VSWHERE_TIMEOUT_MS.load(Ordering::Relaxed));
+    let output = output_with_timeout(&mut cmd, timeout).map_err(|e| {
+        if e.starts_with("timed out") {
+            VSWHERE_TIMED_OUT.store(true, Ordering::Relaxed);
+        }
+        format!("{}: {}", vswhere.display(), e)
+    })?;
+
+    parse_vswhere(&output.stdout).ok_or_else(|| {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        match stderr.trim() {
+            "" => format!("unreadable output ({})", output.status),
+            msg => format!("unreadable output ({}): {}", output.status, msg),
+        }
+    })
+}
+
+/// Like `Command::output()`, but kills the child once `timeout` has passed
+/// (`Duration::ZERO` waits forever). Stdout/stderr are drained on threads so
+/// a chatty child can't block on a full pipe while we wait
+pub fn output_with_timeout(cmd: &mut Command, timeout: Duration) -> Result<Output, String> {
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("cannot run: {}", e))?;
 
-    let output = Command::new(&vswhere)
-        .args(["-all", "-format", "json", "-utf8"])
-        .output()
-        .ok()?;
+    let drain = |pipe: Option<Box<dyn Read + Send>>| {
+        std::thread::spawn(move || {
+            let mut buf = Vec::new();
+            if let Some(mut p) = pipe {
+                let _ = p.read_to_end(&mut buf);
+            }
+            buf
+        })
+    };
+    let stdout = drain(child.stdout.take().map(|p| Box::new(p) as _));
+    let stderr = drain(child.stderr.take().map(|p| Box::new(p) as _));
+
+    let start = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if !timeout.is_zero() && start.elapsed() >= timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("timed out after {:.1}s (killed)", timeout.as_secs_f64()));
+            }
+            Ok(None) => std::thread::sleep(Duration::from_millis(10)),
+            Err(e) => return Err(format!("wait failed: {}", e)),
+        }
+    };
 
-    let entries: Vec<VsWhereEntry> = serde_json::from_slice(&output.stdout).ok()?;
-    
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+/// Detect VS installation via vswhere
+/// If vs_year is Some, filter by year (2019, 2022, etc.)
+/// If toolset is Some, require that MSVC version (e.g. "14.29.30133")
+/// If prerelease is true, Preview installs are considered too
+/// If product is Some, only that edition (Community, BuildTools, ...) is considered
+pub fn detect_vs(
+    vswhere: &Path,
+    prerelease: bool,
+    product: Option<Product>,
+    vs_year: Option<u16>,
+    toolset: Option<&str>,
+) -> Option<VsInfo> {
+    pick_vs(run_vswhere(vswhere, prerelease, product)?, vs_year, toolset)
+}
+
+/// Newest usable install among vswhere `entries`, optionally of one VS year
+fn pick_vs(entries: Vec<VsWhereEntry>, vs_year: Option<u16>, toolset: Option<&str>) -> Option<VsInfo> {
     // Filter by year if specified
     let filtered: Vec<_> = if let Some(year) = vs_year {
         let major = match year {
@@ -107,32 +330,21 @@ pub fn detect_vs(vs_year: Option<u16>) -> Option<VsInfo> {
         entries
     };
 
-    // Sort by version descending (latest first)
+    // Sort by version descending (latest first), same-version installs by path
     let mut sorted = filtered;
-    sorted.sort_by(|a, b| b.installation_version.cmp(&a.installation_version));
+    sorted.sort_by(|a, b| {
+        version_order(&b.installation_version, &a.installation_version)
+            .then_with(|| a.installation_path.cmp(&b.installation_path))
+    });
 
     // Try to build VsInfo from first valid entry
-    sorted.into_iter().find_map(build_vs_info)
+    sorted.into_iter().find_map(|e| build_vs_info(e, toolset))
 }
 
-/// List all installed VS versions (for error messages)
-pub fn list_vs_versions() -> Vec<(u16, String)> {
-    let vswhere = PathBuf::from(r"C:\Program Files (x86)\Microsoft Visual Studio\Installer\vswhere.exe");
-    if !vswhere.exists() {
+/// List all installed VS versions as (year, version, product) (for error messages)
+pub fn list_vs_versions(vswhere: &Path, prerelease: bool, product: Option<Product>) -> Vec<(u16, String, String)> {
+    let Some(entries) = run_vswhere(vswhere, prerelease, product) else {
         return vec![];
-    }
-
-    let output = match Command::new(&vswhere)
-        .args(["-all", "-format", "json", "-utf8"])
-        .output()
-    {
-        Ok(o) => o,
-        Err(_) => return vec![],
-    };
-
-    let entries: Vec<VsWhereEntry> = match serde_json::from_slice(&output.stdout) {
-        Ok(e) => e,
-        Err(_) => return vec![],
     };
 
     entries.into_iter()
@@ -146,62 +358,418 @@ pub fn list_vs_versions() -> Vec<(u16, String)> {
             } else {
                 return None;
             };
-            Some((year, e.installation_version))
+            let product = e.product();
+            Some((year, e.installation_version, product))
         })
         .collect()
 }
 
-/// Find Windows 10/11 SDK
-pub fn detect_sdk() -> Option<SdkInfo> {
-    let sdk_path = reg_find(r"Microsoft\Microsoft SDKs\Windows\v10.0", "InstallationFolder")?;
-    let root = PathBuf::from(sdk_path);
-    let inc = root.join("include");
-    if !inc.exists() {
-        return None;
-    }
+/// List all VS installations as (version, product, install path), including ones without a usable toolset
+pub fn list_vs_installs(vswhere: &Path, prerelease: bool, product: Option<Product>) -> Vec<(String, String, PathBuf)> {
+    run_vswhere(vswhere, prerelease, product)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|e| {
+            let product = e.product();
+            (e.installation_version, product, PathBuf::from(e.installation_path))
+        })
+        .collect()
+}
 
-    // Find latest 10.x with winsdkver.h
-    let mut versions: Vec<_> = std::fs::read_dir(&inc)
-        .ok()?
+/// `InstallationVersion` from `Common7\IDE\devenv.isolation.ini` (absent in Build Tools layouts)
+fn isolation_version(install: &Path) -> Option<String> {
+    let ini = std::fs::read_to_string(install.join("Common7").join("IDE").join("devenv.isolation.ini")).ok()?;
+    ini.lines()
+        .filter_map(|l| l.split_once('='))
+        .find(|(k, _)| k.trim().eq_ignore_ascii_case("InstallationVersion"))
+        .map(|(_, v)| v.trim().to_string())
+}
+
+/// Build VsInfo from an installation directory, bypassing vswhere (portable layouts, `--vs-path`)
+/// The version comes from `devenv.isolation.ini` when present, else it is left blank
+pub fn vs_from_path(install: &Path, toolset: Option<&str>) -> Option<VsInfo> {
+    let entry = VsWhereEntry {
+        installation_path: install.display().to_string(),
+        installation_version: isolation_version(install).unwrap_or_default(),
+        product_id: String::new(),
+    };
+    build_vs_info(entry, toolset)
+}
+
+/// Default VC++ redistributable version from `Microsoft.VCRedistVersion[.v14x].default.txt`,
+/// if its `VC\Redist\MSVC\<ver>` directory exists
+pub fn redist_version(vs: &VsInfo) -> Option<String> {
+    let aux = vs.vc.join("Auxiliary").join("Build");
+    read_marker(&aux, "VCRedistVersion")
+        .filter(|v| !v.is_empty() && vs.vc.join("Redist").join("MSVC").join(v).is_dir())
+}
+
+/// List MSVC toolset versions installed side by side under `VC\Tools\MSVC` (newest first)
+pub fn list_toolsets(vs: &VsInfo) -> Vec<String> {
+    toolsets_in(&vs.install)
+}
+
+/// List MSVC toolset versions under `<install>\VC\Tools\MSVC` (newest first)
+pub fn toolsets_in(install: &Path) -> Vec<String> {
+    let Ok(rd) = std::fs::read_dir(install.join("VC").join("Tools").join("MSVC")) else {
+        return vec![];
+    };
+    let mut versions: Vec<String> = rd
         .filter_map(|e| e.ok())
         .filter(|e| e.path().is_dir())
-        .filter(|e| {
-            let name = e.file_name();
-            let name = name.to_string_lossy();
-            name.starts_with("10.") && e.path().join("um").join("winsdkver.h").exists()
-        })
+        .map(|e| e.file_name().to_string_lossy().to_string())
         .collect();
+    versions.sort_by(|a, b| version_order(b, a));
+    versions
+}
 
-    versions.sort_by(|a, b| b.file_name().cmp(&a.file_name()));
-    let version = versions.first()?.file_name().to_string_lossy().to_string();
+/// List 10.x version directories under `dir` that satisfy `valid` (oldest first)
+fn sdk_versions(dir: &Path, valid: impl Fn(&Path) -> bool) -> Vec<String> {
+    let Ok(rd) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut versions: Vec<String> = rd
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_dir())
+        .filter(|e| e.file_name().to_string_lossy().starts_with("10.") && valid(&e.path()))
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .collect();
+    versions.sort_by(|a, b| version_order(a, b));
+    versions
+}
+
+/// Numeric sort key for dotted versions ("10.0.9200.0" < "10.0.10240.0")
+fn version_key(v: &str) -> Vec<u32> {
+    v.split('.').map(|p| p.parse().unwrap_or(0)).collect()
+}
+
+/// Total order on version strings: numeric, then textual for equal keys ("10.0" vs "10.00"),
+/// so results never depend on `read_dir` order
+fn version_order(a: &str, b: &str) -> std::cmp::Ordering {
+    version_key(a).cmp(&version_key(b)).then_with(|| a.cmp(b))
+}
+
+/// Find Windows 10/11 SDK
+/// If version is Some, use exactly that SDK (e.g. "10.0.19041.0")
+pub fn detect_sdk(select: SdkSelect, version: Option<&str>) -> Option<SdkInfo> {
+    sdk_from_root(&sdk_root()?, select, version)
+}
+
+/// Windows SDK root from registry
+pub fn sdk_root() -> Option<PathBuf> {
+    reg_find(r"Microsoft\Microsoft SDKs\Windows\v10.0", "InstallationFolder").map(PathBuf::from)
+}
+
+/// SDK version has headers installed
+fn sdk_has_headers(ver_dir: &Path) -> bool {
+    ver_dir.join("um").join("winsdkver.h").exists()
+}
+
+/// List all installed Windows SDK versions with headers (newest first)
+pub fn list_sdks() -> Vec<String> {
+    let Some(root) = sdk_root() else {
+        return vec![];
+    };
+    let mut versions = sdk_versions(&root.join("include"), sdk_has_headers);
+    versions.sort_by(|a, b| version_order(b, a));
+    versions
+}
+
+/// Find SDK version under a Windows Kits root (pinned, else newest or oldest per `select`)
+/// Without versioned headers, a flat `include\um` layout (8.1, repackaged SDKs) gives an
+/// empty version; otherwise falls back to the `Lib` tree
+pub fn sdk_from_root(root: &Path, select: SdkSelect, version: Option<&str>) -> Option<SdkInfo> {
+    let root = root.to_path_buf();
+
+    if let Some(v) = version {
+        if !sdk_has_headers(&root.join("include").join(v)) {
+            return None;
+        }
+        return Some(SdkInfo { path: root, version: v.to_string() });
+    }
+
+    // Find 10.x versions with winsdkver.h
+    let mut versions = sdk_versions(&root.join("include"), sdk_has_headers);
+
+    // Flat layout: include\um\winsdkver.h, Lib\um\<arch> (version joins as "")
+    if versions.is_empty() && sdk_has_headers(&root.join("include")) {
+        return Some(SdkInfo { path: root, version: String::new() });
+    }
+
+    // Headers component missing: fall back to versions that only ship libs
+    if versions.is_empty() {
+        versions = sdk_versions(&root.join("Lib"), |p| p.join("um").exists());
+    }
+
+    // sdk_versions lists oldest first
+    let version = match select {
+        SdkSelect::Newest => versions.pop()?,
+        SdkSelect::Oldest => versions.into_iter().next()?,
+    };
 
     Some(SdkInfo { path: root, version })
 }
 
 /// Find Universal CRT
-pub fn detect_ucrt() -> Option<SdkInfo> {
-    let ucrt_path = reg_find(r"Microsoft\Windows Kits\Installed Roots", "KitsRoot10")?;
-    let root = PathBuf::from(ucrt_path);
-    let lib = root.join("Lib");
-    if !lib.exists() {
-        return None;
-    }
+/// If version is Some, use exactly that UCRT (e.g. "10.0.19041.0")
+pub fn detect_ucrt(version: Option<&str>) -> Option<SdkInfo> {
+    ucrt_from_root(&ucrt_root()?, version)
+}
+
+/// Universal CRT (Windows Kits) root from registry
+pub fn ucrt_root() -> Option<PathBuf> {
+    reg_find(r"Microsoft\Windows Kits\Installed Roots", "KitsRoot10").map(PathBuf::from)
+}
+
+/// List UCRT versions (10.x with ucrt.lib) under a Windows Kits root (newest first)
+pub fn ucrt_versions(root: &Path) -> Vec<String> {
+    let mut versions = sdk_versions(&root.join("Lib"), ucrt_has_lib);
+    versions.sort_by(|a, b| version_order(b, a));
+    versions
+}
+
+/// List all installed UCRT versions (newest first)
+pub fn list_ucrts() -> Vec<String> {
+    ucrt_root().map(|root| ucrt_versions(&root)).unwrap_or_default()
+}
 
-    // Find latest 10.x with ucrt.lib
-    let mut versions: Vec<_> = std::fs::read_dir(&lib)
+/// UCRT version has ucrt.lib installed
+fn ucrt_has_lib(ver_dir: &Path) -> bool {
+    ver_dir.join("ucrt").join("x64").join("ucrt.lib").exists()
+}
+
+/// Find UCRT version under a Windows Kits root (pinned, else newest)
+pub fn ucrt_from_root(root: &Path, version: Option<&str>) -> Option<SdkInfo> {
+    let version = match version {
+        Some(v) => {
+            if !ucrt_has_lib(&root.join("Lib").join(v)) {
+                return None;
+            }
+            v.to_string()
+        }
+        None => ucrt_versions(root).into_iter().next()?,
+    };
+    Some(SdkInfo { path: root.to_path_buf(), version })
+}
+
+/// Find the 64-bit .NET Framework 4.x: `Microsoft.NET\Framework64` and its newest `v4.*` dir.
+/// Uses `InstallRoot` from the registry, falling back to `%SystemRoot%`
+pub fn detect_framework() -> Option<SdkInfo> {
+    let path = reg_find(r"Microsoft\.NETFramework", "InstallRoot")
+        .map(PathBuf::from)
+        .filter(|p| p.ends_with("Framework64"))
+        .unwrap_or_else(|| {
+            let root = std::env::var_os("SystemRoot").unwrap_or_else(|| r"C:\Windows".into());
+            PathBuf::from(root).join("Microsoft.NET").join("Framework64")
+        });
+    let version = std::fs::read_dir(&path)
         .ok()?
-        .filter_map(|e| e.ok())
+        .flatten()
         .filter(|e| e.path().is_dir())
-        .filter(|e| {
-            let name = e.file_name();
-            let name = name.to_string_lossy();
-            name.starts_with("10.")
-                && e.path().join("ucrt").join("x64").join("ucrt.lib").exists()
+        .filter_map(|e| e.file_name().into_string().ok())
+        .filter(|n| n.starts_with("v4."))
+        .max_by(|a, b| version_order(&a[1..], &b[1..]))?;
+    Some(SdkInfo { path, version })
+}
+
+/// Host toolset that has a `target` cl.exe: `host` itself, else one it can run under
+/// emulation - ARM64 tries Hostarm64, Hostx64, then Hostx86; x64 tries Hostx64, then Hostx86
+pub fn compiler_host(vs: &VsInfo, host: Arch, target: Arch) -> Option<Arch> {
+    let chain: &[Arch] = match host {
+        Arch::Arm64 => &[Arch::Arm64, Arch::X64, Arch::X86],
+        Arch::X64 => &[Arch::X64, Arch::X86],
+        _ => &[host],
+    };
+    chain.iter().copied().find(|h| {
+        vs.tools.join("bin").join(h.host_dir()).join(target.bin_dir()).join("cl.exe").exists()
+    })
+}
+
+/// SDK `um` and UCRT lib directories missing for `target`, as (component, dir).
+/// Per-arch libs (ARM64 in particular) are optional install components that
+/// `build_env` would otherwise drop from LIB without a word
+pub fn missing_target_libs(sdk: Option<&SdkInfo>, ucrt: Option<&SdkInfo>, target: Arch) -> Vec<(&'static str, PathBuf)> {
+    let lib_dir = target.lib_dir();
+    let sdk_lib = sdk.map(|s| ("Windows SDK", s.path.join("Lib").join(&s.version).join("um").join(&lib_dir)));
+    let ucrt_lib = ucrt.map(|u| ("UCRT", u.path.join("Lib").join(&u.version).join("ucrt").join(&lib_dir)));
+    sdk_lib.into_iter().chain(ucrt_lib).filter(|(_, dir)| !dir.exists()).collect()
+}
+
+/// Check which target arches can be built from `bin\Host<host>`
+/// A target is usable when its cl.exe and the VC++/SDK/UCRT libs are all present
+pub fn arch_support(vs: &VsInfo, host: Arch, sdk: Option<&SdkInfo>, ucrt: Option<&SdkInfo>) -> Vec<ArchSupport> {
+    let bin = vs.tools.join("bin").join(host.host_dir());
+
+    // Target dirs actually shipped for this host
+    let installed: Vec<String> = std::fs::read_dir(&bin)
+        .map(|rd| {
+            rd.filter_map(|e| e.ok())
+                .filter(|e| e.path().is_dir())
+                .map(|e| e.file_name().to_string_lossy().to_lowercase())
+                .collect()
         })
-        .collect();
+        .unwrap_or_default();
 
-    versions.sort_by(|a, b| b.file_name().cmp(&a.file_name()));
-    let version = versions.first()?.file_name().to_string_lossy().to_string();
+    [Arch::X64, Arch::X86, Arch::Arm64, Arch::Arm64ec, Arch::Arm]
+        .into_iter()
+        .map(|target| {
+            let compiler = installed.iter().any(|d| d == target.bin_dir())
+                && bin.join(target.bin_dir()).join("cl.exe").exists();
 
-    Some(SdkInfo { path: root, version })
+            let lib_dir = target.lib_dir();
+            let sdk_lib = sdk.is_none_or(|s| {
+                s.path.join("Lib").join(&s.version).join("um").join(&lib_dir).exists()
+            });
+            let ucrt_lib = ucrt.is_none_or(|u| {
+                u.path.join("Lib").join(&u.version).join("ucrt").join(&lib_dir).exists()
+            });
+            let libs = vs.tools.join("lib").join(&lib_dir).exists() && sdk_lib && ucrt_lib;
+
+            ArchSupport { target: target.as_str(), compiler, libs }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::Tree;
+
+    #[test]
+    fn compiler_host_fallback_order() {
+        let tree = Tree::skeleton("fallback");
+        let vs = tree.vs();
+        assert_eq!(compiler_host(&vs, Arch::Arm64, Arch::X64), Some(Arch::Arm64));
+        // No Hostarm64\x86: the x64-hosted x86 compiler is next in line
+        assert_eq!(compiler_host(&vs, Arch::Arm64, Arch::X86), Some(Arch::X64));
+        // x64 can't run arm64-hosted tools, and there is no Hostx64\arm64
+        assert_eq!(compiler_host(&vs, Arch::X64, Arch::Arm64), None);
+    }
+
+    /// Stand-in for a hung vswhere: a command that runs for ~5 seconds
+    fn slow_command() -> Command {
+        if cfg!(windows) {
+            let mut cmd = Command::new("ping");
+            cmd.args(["-n", "6", "127.0.0.1"]);
+            cmd
+        } else {
+            let mut cmd = Command::new("sleep");
+            cmd.arg("5");
+            cmd
+        }
+    }
+
+    const VSWHERE_JSON: &str = r#"[{"installationPath": "C:\\VS", "installationVersion": "17.9.34607.119"}]"#;
+
+    fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|u| if big_endian { u.to_be_bytes() } else { u.to_le_bytes() })
+            .collect()
+    }
+
+    fn parsed_path(bytes: &[u8]) -> Option<String> {
+        parse_vswhere(bytes)?.into_iter().next().map(|e| e.installation_path)
+    }
+
+    #[test]
+    fn parse_vswhere_utf16() {
+        for big_endian in [false, true] {
+            let bom: &[u8] = if big_endian { &[0xFE, 0xFF] } else { &[0xFF, 0xFE] };
+            let body = utf16(VSWHERE_JSON, big_endian);
+            let with_bom = [bom, &body].concat();
+            assert_eq!(parsed_path(&with_bom).as_deref(), Some(r"C:\VS"), "BOM, big endian: {}", big_endian);
+            assert_eq!(parsed_path(&body).as_deref(), Some(r"C:\VS"), "no BOM, big endian: {}", big_endian);
+        }
+    }
+
+    #[test]
+    fn parse_vswhere_utf8_bom() {
+        let bytes = [&[0xEF, 0xBB, 0xBF], VSWHERE_JSON.as_bytes()].concat();
+        assert_eq!(parsed_path(&bytes).as_deref(), Some(r"C:\VS"));
+    }
+
+    #[test]
+    fn buildtools_only_install_found() {
+        // Without -p, vswhere is asked for every product: its default omits Build Tools
+        let args = vswhere_args(false, None);
+        assert!(args.windows(2).any(|w| w == ["-products", "*"]));
+
+        let root = std::env::temp_dir().join(format!("vcv-buildtools-{}", std::process::id()));
+        let tools = root.join("VC").join("Tools").join("MSVC").join("14.40.33807");
+        std::fs::create_dir_all(&tools).unwrap();
+        let fixture = format!(
+            r#"[{{"installationPath": {}, "installationVersion": "17.9.34607.119",
+                "productId": "Microsoft.VisualStudio.Product.BuildTools"}}]"#,
+            serde_json::to_string(&root.display().to_string()).unwrap()
+        );
+        let entries = parse_vswhere(fixture.as_bytes()).unwrap();
+        assert_eq!(entries[0].product(), "BuildTools");
+        let vs = pick_vs(entries, Some(2022), Some("14.40.33807"));
+        let _ = std::fs::remove_dir_all(&root);
+        assert_eq!(vs.map(|v| v.tools), Some(tools));
+    }
+
+    #[test]
+    fn markerless_arm64_hosted_toolset() {
+        // No marker files and no Hostx64: the arm64-hosted toolset still counts, the empty newer one doesn't
+        let root = std::env::temp_dir().join(format!("vcv-arm64host-{}", std::process::id()));
+        let msvc = root.join("VC").join("Tools").join("MSVC");
+        let cl_dir = msvc.join("14.40.33807").join("bin").join("Hostarm64").join("arm64");
+        std::fs::create_dir_all(&cl_dir).unwrap();
+        std::fs::create_dir_all(msvc.join("14.99.0").join("bin")).unwrap();
+        std::fs::write(cl_dir.join("cl.exe"), "").unwrap();
+        let vs = vs_from_path(&root, None);
+        let _ = std::fs::remove_dir_all(&root);
+        assert_eq!(vs.map(|v| v.tools_ver).as_deref(), Some("14.40.33807"));
+    }
+
+    #[test]
+    fn toolset_from_v144_marker() {
+        // Only the v144 marker, naming a toolset without cl.exe (the cl.exe scan alone picks the older one)
+        let tree = Tree::new("v144");
+        let vc = Path::new("VC");
+        let msvc = vc.join("Tools").join("MSVC");
+        tree.dirs(&[msvc.join("14.50.35717").join("include")]);
+        tree.files(&[
+            (vc.join("Auxiliary").join("Build").join("Microsoft.VCToolsVersion.v144.default.txt"), "14.50.35717"),
+            (msvc.join("14.40.33807").join("bin").join("Hostx64").join("x64").join("cl.exe"), ""),
+        ]);
+        let vs = vs_from_path(&tree.root, None);
+        assert_eq!(vs.map(|v| v.tools_ver).as_deref(), Some("14.50.35717"));
+    }
+
+    #[test]
+    fn flat_sdk_layout() {
+        // include\um and Lib\um\<arch> without a version directory
+        let tree = Tree::new("flat-sdk");
+        tree.dirs(&[Path::new("include").join("shared"), Path::new("Lib").join("um").join("x64")]);
+        tree.files(&[(Path::new("include").join("um").join("winsdkver.h"), "")]);
+
+        let sdk = sdk_from_root(&tree.root, SdkSelect::Newest, None);
+        assert_eq!(sdk.map(|s| s.version).as_deref(), Some(""));
+        // A pinned version needs its own headers: the flat ones don't count
+        assert!(sdk_from_root(&tree.root, SdkSelect::Newest, Some("10.0.22621.0")).is_none());
+    }
+
+    #[test]
+    fn missing_arm64_libs() {
+        let tree = Tree::skeleton("missing-libs");
+        let (sdk, ucrt) = (tree.sdk(), tree.ucrt());
+        assert!(missing_target_libs(Some(&sdk), Some(&ucrt), Arch::Arm64).is_empty());
+
+        // ARM64 libs are an optional SDK component: this SDK only has x64
+        let x64_only = SdkInfo { path: tree.root.join("Flat"), version: String::new() };
+        tree.dirs(&[x64_only.path.join("Lib").join("um").join("x64")]);
+        let missing = missing_target_libs(Some(&x64_only), Some(&ucrt), Arch::Arm64);
+        assert_eq!(missing, [("Windows SDK", x64_only.path.join("Lib").join("um").join("arm64"))]);
+        assert!(missing_target_libs(Some(&x64_only), None, Arch::X64).is_empty());
+    }
+
+    #[test]
+    fn hung_command_killed_after_timeout() {
+        let start = Instant::now();
+        let result = output_with_timeout(&mut slow_command(), Duration::from_millis(200));
+        assert!(result.is_err_and(|e| e.starts_with("timed out")));
+        assert!(start.elapsed() < Duration::from_secs(3));
+    }
 }
@@ -4,75 +4,358 @@
 //!
 //! ## Purpose
 //! Builds the complete environment needed for MSVC compilation:
-//! - PATH: compiler binaries (cl.exe, link.exe) and SDK tools
+//! - PATH: compiler binaries (cl.exe, link.exe), SDK tools, MSBuild and IDE tools
 //! - INCLUDE: headers (VC++, ATL/MFC, SDK, UCRT)
 //! - LIB: static libraries for linking
 //! - LIBPATH: assembly references (.NET metadata)
 //!
 //! ## Key Functions
 //! - `build_env()` - Main function that assembles all paths based on host/target arch
+//! - `persist()` - Write to the user/machine environment in the registry
+//! - `add_cmd_autorun()` - Run a script on every cmd.exe start (`--install cmd --write`)
+//! - `EnvOpts` - Optional components (Spectre-mitigated libs, clang-cl, UWP/Store libs, ...)
+//! - `Env::merged_vars()` / `Env::new_entries()` - Merge with / diff against the current process environment
+//! - `Env::get()` - Value of a single variable (`--get`)
+//! - `Env::missing` - Candidate directories skipped as nonexistent (`--report-missing`)
+//! - `Env::merge_base()` - Layer on top of a base environment (`--base-env`)
+//! - `Env::append_current()` - Add the process's own path lists (`--with-current`)
 //!
 //! ## Dependencies
 //! - `detect` module for VsInfo/SdkInfo structs
+//! - `registry` module for the persisted environment
 //! - `std::collections::BTreeMap` for stable key ordering
 
-use crate::detect::{SdkInfo, VsInfo};
-use crate::Arch;
-use std::collections::BTreeMap;
-use std::path::PathBuf;
+use crate::detect::{self, SdkInfo, VsInfo};
+use crate::registry;
+use crate::{Arch, PersistScope};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+/// Error naming the first two of `names` that differ only in case
+fn case_collision<'a>(names: impl IntoIterator<Item = &'a str>) -> Result<(), String> {
+    let mut seen = HashMap::new();
+    for name in names {
+        if let Some(other) = seen.insert(name.to_ascii_uppercase(), name) {
+            return Err(format!("variables {} and {} differ only in case", other, name));
+        }
+    }
+    Ok(())
+}
 
 /// Assembled environment
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Env {
     pub path: Vec<PathBuf>,
     pub include: Vec<PathBuf>,
     pub lib: Vec<PathBuf>,
     pub libpath: Vec<PathBuf>,
     pub vars: BTreeMap<String, String>,
+    /// Candidate directories `build_env` skipped because they don't exist, as (variable, path)
+    #[serde(skip)]
+    pub missing: Vec<(&'static str, PathBuf)>,
 }
 
 impl Env {
-    fn add_if_exists(lst: &mut Vec<PathBuf>, paths: &[PathBuf]) {
+    /// Full path of the first cl.exe found on PATH
+    pub fn cl_path(&self) -> Option<PathBuf> {
+        self.find_tool("cl.exe")
+    }
+
+    /// Full path of the first `exe` found on PATH
+    pub fn find_tool(&self, exe: &str) -> Option<PathBuf> {
+        self.path.iter().map(|p| p.join(exe)).find(|p| p.exists())
+    }
+
+    /// Remove duplicate entries (case-insensitive, as on Windows), keeping the first occurrence
+    pub fn dedup(&mut self) {
+        for list in [&mut self.path, &mut self.include, &mut self.lib, &mut self.libpath] {
+            let mut seen = std::collections::HashSet::new();
+            list.retain(|p| seen.insert(path_key(&p.to_string_lossy())));
+        }
+    }
+
+    /// The path lists with their variable names
+    pub fn lists(&self) -> [(&'static str, &Vec<PathBuf>); 4] {
+        [("PATH", &self.path), ("INCLUDE", &self.include), ("LIB", &self.lib), ("LIBPATH", &self.libpath)]
+    }
+
+    /// Entries of each path list that are not already in the current process's variable
+    pub fn new_entries(&self) -> Vec<(&'static str, Vec<&PathBuf>)> {
+        self.lists()
+            .into_iter()
+            .map(|(name, list)| {
+                let current = std::env::var(name).unwrap_or_default();
+                let have: std::collections::HashSet<String> = current.split(';').map(path_key).collect();
+                (name, list.iter().filter(|p| !have.contains(&path_key(&p.to_string_lossy()))).collect())
+            })
+            .collect()
+    }
+
+    /// Value of one variable, matched case-insensitively like Windows does:
+    /// a path list joined with `;`, or an entry of `vars`
+    pub fn get(&self, name: &str) -> Option<String> {
+        if let Some((_, list)) = self.lists().into_iter().find(|(n, _)| n.eq_ignore_ascii_case(name)) {
+            let parts: Vec<String> = list.iter().map(|p| p.display().to_string()).collect();
+            return Some(parts.join(";"));
+        }
+        self.vars.iter().find(|(k, _)| k.eq_ignore_ascii_case(name)).map(|(_, v)| v.clone())
+    }
+
+    /// Layer this environment on top of `base` (the flat `fmt_json` shape, `--base-env`):
+    /// path lists keep their entries first and gain the base's (arrays or `;`-joined strings),
+    /// other base variables are kept unless already set here; base keys differing only in case are an error
+    pub fn merge_base(&mut self, base: &BTreeMap<String, serde_json::Value>) -> Result<(), String> {
+        case_collision(base.keys().map(String::as_str))?;
+        for (key, value) in base {
+            let entries: Vec<String> = match value {
+                serde_json::Value::String(s) => s.split(';').filter(|e| !e.is_empty()).map(String::from).collect(),
+                serde_json::Value::Array(items) => items
+                    .iter()
+                    .map(|i| i.as_str().map(String::from).ok_or_else(|| format!("{}: expected strings", key)))
+                    .collect::<Result<_, _>>()?,
+                _ => return Err(format!("{}: expected a string or an array of strings", key)),
+            };
+            let list = match key.to_ascii_uppercase().as_str() {
+                "PATH" => &mut self.path,
+                "INCLUDE" => &mut self.include,
+                "LIB" => &mut self.lib,
+                "LIBPATH" => &mut self.libpath,
+                _ => {
+                    if !self.vars.keys().any(|k| k.eq_ignore_ascii_case(key)) {
+                        self.vars.insert(key.clone(), entries.join(";"));
+                    }
+                    continue;
+                }
+            };
+            list.extend(entries.into_iter().map(PathBuf::from));
+        }
+        self.dedup();
+        Ok(())
+    }
+
+    /// Fail when two emitted names (`prefix` + variable) differ only in case (`Path` next to `PATH`):
+    /// Windows treats them as one, so the result would depend on which one the shell sets last
+    pub fn check_keys(&self, prefix: &str) -> Result<(), String> {
+        let names: Vec<String> = self
+            .lists()
+            .iter()
+            .map(|(n, _)| *n)
+            .chain(self.vars.keys().map(String::as_str))
+            .map(|n| format!("{}{}", prefix, n))
+            .collect();
+        case_collision(names.iter().map(String::as_str))
+    }
+
+    /// Append the current process's PATH/INCLUDE/LIB/LIBPATH entries (`;`-separated) to the
+    /// matching lists, giving the state after the environment is applied
+    pub fn append_current(&mut self) {
+        for (name, list) in [
+            ("PATH", &mut self.path),
+            ("INCLUDE", &mut self.include),
+            ("LIB", &mut self.lib),
+            ("LIBPATH", &mut self.libpath),
+        ] {
+            let current = std::env::var(name).unwrap_or_default();
+            list.extend(current.split(';').filter(|e| !e.is_empty()).map(PathBuf::from));
+        }
+        self.dedup();
+    }
+
+    /// Variables to set in a process environment: the path lists prepended to the
+    /// current value of the same variable (when `inherit`), followed by `vars`
+    pub fn merged_vars(&self, inherit: bool) -> Vec<(String, String)> {
+        let mut out = Vec::new();
+        for (name, list) in self.lists() {
+            if list.is_empty() {
+                continue;
+            }
+            let mut parts: Vec<String> = list.iter().map(|p| p.display().to_string()).collect();
+            if inherit
+                && let Ok(existing) = std::env::var(name)
+                && !existing.is_empty()
+            {
+                parts.push(existing);
+            }
+            out.push((name.to_string(), parts.join(";")));
+        }
+        out.extend(self.vars.iter().map(|(k, v)| (k.clone(), v.clone())));
+        out
+    }
+
+    /// Append the existing `paths` to list `var`; the others are remembered in `missing`
+    fn add_if_exists(&mut self, var: &'static str, paths: &[PathBuf]) {
         for p in paths {
             if p.exists() {
-                lst.push(p.clone());
+                let list = match var {
+                    "PATH" => &mut self.path,
+                    "INCLUDE" => &mut self.include,
+                    "LIB" => &mut self.lib,
+                    _ => &mut self.libpath,
+                };
+                list.push(p.clone());
+            } else {
+                self.missing.push((var, p.clone()));
             }
         }
     }
 }
 
+/// Add `script` to the current user's cmd.exe `AutoRun`, after any existing command
+/// (`old & "script"`); nothing changes when it is already there
+pub fn add_cmd_autorun(script: &Path) -> Result<(), String> {
+    let key = registry::cmd_processor_key().map_err(|e| format!("Cannot open the cmd.exe AutoRun key: {}", e))?;
+    let quoted = format!("\"{}\"", script.display());
+    let current = registry::env_get(&key, "AutoRun").unwrap_or_default();
+    if current.contains(&quoted) {
+        return Ok(());
+    }
+    let value = if current.trim().is_empty() { quoted } else { format!("{} & {}", current, quoted) };
+    registry::env_set(&key, "AutoRun", &value, false).map_err(|e| format!("Cannot set AutoRun: {}", e))
+}
+
+/// Write the environment to the persisted user/machine environment and broadcast the change
+/// Path lists are prepended to the persisted value (entries already there are skipped),
+/// other variables are overwritten
+pub fn persist(env: &Env, scope: PersistScope) -> Result<(), String> {
+    let key = registry::env_key(scope).map_err(|e| match scope {
+        PersistScope::User => format!("Cannot open HKCU\\Environment: {}", e),
+        PersistScope::Machine => format!("Cannot open the machine environment (run elevated): {}", e),
+    })?;
+    let write = |name: &str, value: &str, expand: bool| {
+        registry::env_set(&key, name, value, expand).map_err(|e| format!("Cannot write {}: {}", name, e))
+    };
+
+    for (name, list) in env.lists() {
+        let existing = registry::env_get(&key, name).unwrap_or_default();
+        let have: std::collections::HashSet<String> = existing.split(';').map(path_key).collect();
+        let mut parts: Vec<String> = list
+            .iter()
+            .filter(|p| !have.contains(&path_key(&p.to_string_lossy())))
+            .map(|p| p.display().to_string())
+            .collect();
+        if parts.is_empty() {
+            continue;
+        }
+        if !existing.is_empty() {
+            parts.push(existing);
+        }
+        write(name, &parts.join(";"), true)?;
+    }
+    for (k, v) in &env.vars {
+        write(k, v, false)?;
+    }
+
+    registry::broadcast_env_change();
+    Ok(())
+}
+
+/// Comparison key for a path entry: case-insensitive, without trailing separators (as on Windows)
+fn path_key(p: &str) -> String {
+    p.trim_end_matches(['\\', '/']).to_lowercase()
+}
+
+/// Optional components for `build_env`
+#[derive(Debug, Clone, Default)]
+pub struct EnvOpts {
+    /// Spectre-mitigated libraries ahead of the regular ones
+    pub spectre: bool,
+    /// LLVM bin directory (clang-cl.exe) from the VS "C++ Clang tools" component
+    pub clang: bool,
+    /// CMake and Ninja bundled with the VS "C++ CMake tools" component
+    pub cmake_tools: bool,
+    /// UWP/Store libraries (vcvars `-app_platform=UWP`): `lib\<arch>\store` instead of `lib\<arch>`
+    pub store: bool,
+    /// Leave out the ATL/MFC include and lib directories
+    pub no_atlmfc: bool,
+    /// .NET Framework dir on PATH/LIBPATH plus `FrameworkDir64`/`FrameworkVersion64` (C++/CLI)
+    pub clr: bool,
+    /// Debugging Tools for Windows (windbg, cdb) from `<KitsRoot10>\Debuggers\<host>`
+    pub debuggers: bool,
+    /// Windows Kits root holding `Debuggers`; None = the UCRT's (so `--no-ucrt` needs it set)
+    pub kits_root: Option<PathBuf>,
+    /// C++/WinRT metadata on LIBPATH (SDK WinMDs, `platform.winmd`) plus `WindowsLibPath`/`WindowsSDKLibVersion`
+    pub winrt: bool,
+    /// Minimal environment for compiling: VC++ bin on PATH, VC++/SDK um/UCRT
+    /// includes and libs; no SDK tools, IDE tools, LIBPATH or variables
+    pub compiler_only: bool,
+}
+
+/// `<KitsRoot10>\Debuggers\<host>`: from `opts.kits_root`, else the UCRT's root
+pub fn debuggers_dir(ucrt: Option<&SdkInfo>, host: Arch, opts: &EnvOpts) -> Option<PathBuf> {
+    let root = opts.kits_root.as_ref().or(ucrt.map(|u| &u.path))?;
+    Some(root.join("Debuggers").join(host.as_str()))
+}
+
 /// Build complete environment
-pub fn build_env(vs: &VsInfo, sdk: Option<&SdkInfo>, ucrt: Option<&SdkInfo>, host: Arch, target: Arch) -> Env {
+pub fn build_env(
+    vs: &VsInfo,
+    sdk: Option<&SdkInfo>,
+    ucrt: Option<&SdkInfo>,
+    host: Arch,
+    target: Arch,
+    opts: &EnvOpts,
+) -> Env {
     let mut env = Env::default();
     let tp = &vs.tools;
+    let full = !opts.compiler_only;
 
-    let hd = match host {
-        Arch::X64 => "Hostx64",
-        Arch::X86 => "Hostx86",
-        Arch::Arm64 => "Hostarm64",
-    };
-    let tgt = target.as_str();
+    // The requested host's toolset, or the first one it can run that has a `target` compiler
+    let vc_host = detect::compiler_host(vs, host, target).unwrap_or(host);
+    let hd = vc_host.host_dir();
+    let tlib = target.lib_dir();
 
     // VC++ binaries
-    Env::add_if_exists(&mut env.path, &[tp.join("bin").join(hd).join(tgt)]);
-    if host != target {
-        let host_str = host.as_str();
-        Env::add_if_exists(&mut env.path, &[tp.join("bin").join(hd).join(host_str)]);
-    }
-
-    // VC++ headers & libs
-    Env::add_if_exists(&mut env.include, &[
-        tp.join("include"),
-        tp.join("ATLMFC").join("include"),
-    ]);
-    Env::add_if_exists(&mut env.lib, &[
-        tp.join("lib").join(tgt),
-        tp.join("ATLMFC").join("lib").join(tgt),
-    ]);
-    Env::add_if_exists(&mut env.libpath, &[
-        tp.join("lib").join(tgt),
-        tp.join("ATLMFC").join("lib").join(tgt),
-    ]);
+    env.add_if_exists("PATH", &[tp.join("bin").join(hd).join(target.bin_dir())]);
+    if vc_host.bin_dir() != target.bin_dir() {
+        let host_str = vc_host.as_str();
+        env.add_if_exists("PATH", &[tp.join("bin").join(hd).join(host_str)]);
+    }
+
+    // clang-cl: VC\Tools\Llvm\<host>\bin (Llvm\bin is the x86-hosted build)
+    if opts.clang {
+        let llvm = vs.vc.join("Tools").join("Llvm");
+        let bin = match host {
+            Arch::X64 => llvm.join("x64").join("bin"),
+            Arch::Arm64 => llvm.join("ARM64").join("bin"),
+            _ => llvm.join("bin"),
+        };
+        let bin = if bin.exists() { bin } else { llvm.join("bin") };
+        let clang_cl = bin.join("clang-cl.exe");
+        if clang_cl.exists() {
+            env.vars.insert("VCV_CLANG".into(), clang_cl.display().to_string());
+        }
+        env.add_if_exists("PATH", &[bin]);
+    }
+
+    // VC++ headers & libs (from the toolset and, unless disabled, ATL/MFC)
+    let mut vc_roots = vec![tp.clone()];
+    if !opts.no_atlmfc && full {
+        vc_roots.push(tp.join("ATLMFC"));
+    }
+    let vc_dirs = |sub: &Path| -> Vec<PathBuf> { vc_roots.iter().map(|r| r.join(sub)).collect() };
+    env.add_if_exists("INCLUDE", &vc_dirs(Path::new("include")));
+    if opts.spectre {
+        // Same layout vcvars uses for -vcvars_spectre_libs: lib\spectre\<arch>
+        let spectre = vc_dirs(&Path::new("lib").join("spectre").join(&tlib));
+        env.add_if_exists("LIB", &spectre);
+        if full {
+            env.add_if_exists("LIBPATH", &spectre);
+        }
+    }
+    if opts.store {
+        let store = tp.join("lib").join(&tlib).join("store");
+        env.add_if_exists("LIB", std::slice::from_ref(&store));
+        if full {
+            env.add_if_exists("LIBPATH", &[store.join("references")]);
+        }
+    } else {
+        let libs = vc_dirs(&Path::new("lib").join(&tlib));
+        env.add_if_exists("LIB", &libs);
+        if full {
+            env.add_if_exists("LIBPATH", &libs);
+        }
+    }
 
     // Windows SDK
     if let Some(sdk) = sdk {
@@ -80,19 +363,48 @@ pub fn build_env(vs: &VsInfo, sdk: Option<&SdkInfo>, ucrt: Option<&SdkInfo>, hos
         let sv = &sdk.version;
         let host_str = host.as_str();
 
-        Env::add_if_exists(&mut env.path, &[sp.join("bin").join(sv).join(host_str)]);
-        // Note: ucrt include is added from UCRT section, not here
-        Env::add_if_exists(&mut env.include, &[
+        // rc.exe/mc.exe for the host, target-arch tools (signtool, mt) on cross builds,
+        // then the versionless bin\<host> layout of older SDKs
+        if full {
+            env.add_if_exists("PATH", &[sp.join("bin").join(sv).join(host_str)]);
+            if host.bin_dir() != target.bin_dir() {
+                env.add_if_exists("PATH", &[sp.join("bin").join(sv).join(target.bin_dir())]);
+            }
+            env.add_if_exists("PATH", &[sp.join("bin").join(host_str)]);
+        }
+        // Note: ucrt include is added from UCRT section, not here.
+        // um headers pull in shared\, so both stay in compiler-only mode.
+        env.add_if_exists("INCLUDE", &[
             sp.join("include").join(sv).join("um"),
             sp.join("include").join(sv).join("shared"),
-            sp.join("include").join(sv).join("winrt"),
-            sp.join("include").join(sv).join("cppwinrt"),
-        ]);
-        Env::add_if_exists(&mut env.lib, &[sp.join("lib").join(sv).join("um").join(tgt)]);
-        Env::add_if_exists(&mut env.libpath, &[
-            sp.join("UnionMetadata").join(sv),
-            sp.join("References").join(sv),
         ]);
+        if full {
+            env.add_if_exists("INCLUDE", &[
+                sp.join("include").join(sv).join("winrt"),
+                sp.join("include").join(sv).join("cppwinrt"),
+            ]);
+        }
+        if opts.spectre {
+            env.add_if_exists("LIB", &[sp.join("Lib").join(sv).join("um").join(&tlib).join("spectre")]);
+        }
+        env.add_if_exists("LIB", &[sp.join("Lib").join(sv).join("um").join(&tlib)]);
+        if full {
+            env.add_if_exists("LIBPATH", &[
+                sp.join("UnionMetadata").join(sv),
+                sp.join("References").join(sv),
+            ]);
+        }
+        if opts.store && full {
+            env.add_if_exists("LIBPATH", &[sp.join("References").join("CommonConfiguration").join("Neutral")]);
+        }
+        // C++/WinRT: the SDK's WinMDs (UnionMetadata, API contracts) and the VC++ platform.winmd
+        if opts.winrt {
+            env.add_if_exists("LIBPATH", &[
+                sp.join("UnionMetadata").join(sv),
+                sp.join("References").join(sv),
+                tp.join("lib").join("x86").join("store").join("references"),
+            ]);
+        }
     }
 
     // UCRT
@@ -100,21 +412,84 @@ pub fn build_env(vs: &VsInfo, sdk: Option<&SdkInfo>, ucrt: Option<&SdkInfo>, hos
         let up = &ucrt.path;
         let uv = &ucrt.version;
 
-        Env::add_if_exists(&mut env.include, &[up.join("include").join(uv).join("ucrt")]);
-        Env::add_if_exists(&mut env.lib, &[up.join("lib").join(uv).join("ucrt").join(tgt)]);
+        env.add_if_exists("INCLUDE", &[up.join("include").join(uv).join("ucrt")]);
+        if opts.spectre {
+            env.add_if_exists("LIB", &[up.join("Lib").join(uv).join("ucrt").join(&tlib).join("spectre")]);
+        }
+        env.add_if_exists("LIB", &[up.join("Lib").join(uv).join("ucrt").join(&tlib)]);
+    }
+
+    // .NET Framework for C++/CLI (/clr): mscorlib & co. on LIBPATH, like vcvars
+    if opts.clr
+        && let Some(fw) = detect::detect_framework()
+    {
+        let dir = fw.path.join(&fw.version);
+        env.add_if_exists("PATH", std::slice::from_ref(&dir));
+        env.add_if_exists("LIBPATH", &[dir]);
+        for (dir_var, ver_var) in [("FrameworkDir", "FrameworkVersion"), ("FrameworkDir64", "FrameworkVersion64")] {
+            env.vars.insert(dir_var.into(), format!("{}\\", fw.path.display()));
+            env.vars.insert(ver_var.into(), fw.version.clone());
+        }
+    }
+
+    // MSBuild and IDE tools (msbuild, devenv)
+    let ide = vs.install.join("Common7").join("IDE");
+    if full {
+        env.add_if_exists("PATH", &[
+            vs.install.join("MSBuild").join("Current").join("Bin"),
+            ide.clone(),
+            vs.install.join("Common7").join("Tools"),
+        ]);
+    }
+
+    // Bundled CMake/Ninja, after everything else
+    if opts.cmake_tools {
+        let cmake = ide.join("CommonExtensions").join("Microsoft").join("CMake");
+        env.add_if_exists("PATH", &[cmake.join("CMake").join("bin"), cmake.join("Ninja")]);
+    }
+
+    // Debugging Tools for Windows live under the Windows Kits root
+    if opts.debuggers
+        && let Some(dbg) = debuggers_dir(ucrt, host, opts)
+    {
+        env.add_if_exists("PATH", &[dbg]);
+    }
+
+    env.dedup();
+
+    if !full {
+        return env;
     }
 
     // Standard variables
     env.vars.insert("VSINSTALLDIR".into(), format!("{}\\", vs.install.display()));
     env.vars.insert("VCINSTALLDIR".into(), format!("{}\\", vs.vc.display()));
+    if ide.exists() {
+        env.vars.insert("DevEnvDir".into(), format!("{}\\", ide.display()));
+    }
     env.vars.insert("VCToolsInstallDir".into(), format!("{}\\", tp.display()));
     env.vars.insert("VCToolsVersion".into(), vs.tools_ver.clone());
+    if let Some(redist) = detect::redist_version(vs) {
+        let dir = vs.vc.join("Redist").join("MSVC").join(&redist);
+        env.vars.insert("VCToolsRedistDir".into(), format!("{}\\", dir.display()));
+        env.vars.insert("VCRedistVersion".into(), redist);
+    }
     env.vars.insert("VisualStudioVersion".into(), "17.0".into());
-    env.vars.insert("Platform".into(), tgt.into());
+    env.vars.insert("Platform".into(), target.as_str().into());
 
     if let Some(sdk) = sdk {
         env.vars.insert("WindowsSdkDir".into(), format!("{}\\", sdk.path.display()));
-        env.vars.insert("WindowsSDKVersion".into(), format!("{}\\", sdk.version));
+        if !sdk.version.is_empty() {
+            env.vars.insert("WindowsSDKVersion".into(), format!("{}\\", sdk.version));
+        }
+        if opts.winrt {
+            let union = sdk.path.join("UnionMetadata").join(&sdk.version);
+            let refs = sdk.path.join("References").join(&sdk.version);
+            env.vars.insert("WindowsLibPath".into(), format!("{};{}", union.display(), refs.display()));
+            if !sdk.version.is_empty() {
+                env.vars.insert("WindowsSDKLibVersion".into(), format!("{}\\", sdk.version));
+            }
+        }
     }
 
     if let Some(ucrt) = ucrt {
@@ -124,3 +499,164 @@ pub fn build_env(vs: &VsInfo, sdk: Option<&SdkInfo>, ucrt: Option<&SdkInfo>, hos
 
     env
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{Tree, REDIST_VER, SDK_VER};
+
+    /// Environment for `host` -> `target` from the skeleton
+    fn skeleton_env(tree: &Tree, host: Arch, target: Arch) -> Env {
+        let (vs, sdk, ucrt) = (tree.vs(), tree.sdk(), tree.ucrt());
+        build_env(&vs, Some(&sdk), Some(&ucrt), host, target, &EnvOpts::default())
+    }
+
+    #[test]
+    fn arm64_native() {
+        let tree = Tree::skeleton("arm64-native");
+        let env = skeleton_env(&tree, Arch::Arm64, Arch::Arm64);
+        let bin = tree.tools().join("bin").join("Hostarm64");
+        assert_eq!(env.cl_path(), Some(bin.join("arm64").join("cl.exe")));
+        assert_eq!(env.path.iter().filter(|p| p.starts_with(tree.tools())).count(), 1);
+        assert!(env.path.contains(&tree.kits().join("bin").join(SDK_VER).join("arm64")));
+        assert!(env.lib.contains(&tree.tools().join("lib").join("arm64")));
+        assert_eq!(env.vars.get("Platform").map(String::as_str), Some("arm64"));
+    }
+
+    #[test]
+    fn arm64_host_x64_target() {
+        let tree = Tree::skeleton("arm64-x64");
+        let env = skeleton_env(&tree, Arch::Arm64, Arch::X64);
+        let bin = tree.tools().join("bin").join("Hostarm64");
+        // Target compiler first, then the host's own tools (DLLs the cross compiler loads)
+        assert_eq!(env.path[..2], [bin.join("x64"), bin.join("arm64")]);
+        let sdk_bin = tree.kits().join("bin").join(SDK_VER);
+        assert!(env.path.contains(&sdk_bin.join("arm64")) && env.path.contains(&sdk_bin.join("x64")));
+        assert!(env.lib.contains(&tree.tools().join("lib").join("x64")));
+        assert_eq!(env.vars.get("Platform").map(String::as_str), Some("x64"));
+    }
+
+    #[test]
+    fn sdk_bin_layout() {
+        // Versioned bin\<ver>\<host> first, the target's on cross builds, then the old bin\<host>
+        let tree = Tree::skeleton("sdk-bin");
+        let bin = tree.kits().join("bin");
+        let sdk_path = |env: &Env| -> Vec<PathBuf> {
+            env.path.iter().filter(|p| p.starts_with(&bin)).cloned().collect()
+        };
+        let native = skeleton_env(&tree, Arch::X64, Arch::X64);
+        assert_eq!(sdk_path(&native), [bin.join(SDK_VER).join("x64"), bin.join("x64")]);
+        let cross = skeleton_env(&tree, Arch::X64, Arch::Arm64);
+        assert_eq!(
+            sdk_path(&cross),
+            [bin.join(SDK_VER).join("x64"), bin.join(SDK_VER).join("arm64"), bin.join("x64")]
+        );
+    }
+
+    #[test]
+    fn flat_sdk_paths() {
+        let tree = Tree::skeleton("env-flat");
+        let flat = SdkInfo { path: tree.root.join("Flat"), version: String::new() };
+        tree.dirs(&[flat.path.join("include").join("um"), flat.path.join("Lib").join("um").join("x64")]);
+        let env = build_env(&tree.vs(), Some(&flat), None, Arch::X64, Arch::X64, &EnvOpts::default());
+        assert!(env.include.contains(&flat.path.join("include").join("um")));
+        assert!(env.lib.contains(&flat.path.join("Lib").join("um").join("x64")));
+    }
+
+    #[test]
+    fn host_fallback_compiler_on_path() {
+        let tree = Tree::skeleton("env-fallback");
+        let (vs, sdk, ucrt) = (tree.vs(), tree.sdk(), tree.ucrt());
+        let env = build_env(&vs, Some(&sdk), Some(&ucrt), Arch::Arm64, Arch::X86, &EnvOpts::default());
+        let hostx64 = tree.tools().join("bin").join("Hostx64");
+        assert_eq!(env.cl_path(), Some(hostx64.join("x86").join("cl.exe")));
+        assert!(env.path.contains(&hostx64.join("x64")));
+    }
+
+    #[test]
+    fn redist_version_from_marker() {
+        let tree = Tree::skeleton("redist");
+        let env = skeleton_env(&tree, Arch::X64, Arch::X64);
+        assert_eq!(env.vars.get("VCRedistVersion").map(String::as_str), Some(REDIST_VER));
+    }
+
+    #[test]
+    fn winrt_references() {
+        let tree = Tree::skeleton("winrt");
+        let (vs, sdk, ucrt) = (tree.vs(), tree.sdk(), tree.ucrt());
+        let opts = EnvOpts { winrt: true, ..EnvOpts::default() };
+        let env = build_env(&vs, Some(&sdk), Some(&ucrt), Arch::X64, Arch::X64, &opts);
+        assert!(env.libpath.contains(&tree.kits().join("References").join(SDK_VER)));
+        assert_eq!(env.vars.get("WindowsSDKLibVersion"), Some(&format!("{}\\", SDK_VER)));
+        assert!(env.vars.contains_key("WindowsLibPath"));
+    }
+
+    #[test]
+    fn debuggers_without_ucrt() {
+        let tree = Tree::skeleton("debuggers");
+        let dbg = tree.kits().join("Debuggers").join("x64");
+        tree.dirs(&[&dbg]);
+        let opts = EnvOpts { debuggers: true, ..EnvOpts::default() };
+        let env = build_env(&tree.vs(), Some(&tree.sdk()), Some(&tree.ucrt()), Arch::X64, Arch::X64, &opts);
+        assert!(env.path.contains(&dbg));
+
+        // --no-ucrt: the Kits root comes from the options instead
+        let env = build_env(&tree.vs(), Some(&tree.sdk()), None, Arch::X64, Arch::X64, &opts);
+        assert!(!env.path.contains(&dbg));
+        let opts = EnvOpts { kits_root: Some(tree.kits()), ..opts };
+        let env = build_env(&tree.vs(), Some(&tree.sdk()), None, Arch::X64, Arch::X64, &opts);
+        assert!(env.path.contains(&dbg));
+    }
+
+    #[test]
+    fn compiler_only() {
+        let tree = Tree::skeleton("compiler-only");
+        let (vs, sdk, ucrt) = (tree.vs(), tree.sdk(), tree.ucrt());
+        let opts = EnvOpts { compiler_only: true, ..EnvOpts::default() };
+        let env = build_env(&vs, Some(&sdk), Some(&ucrt), Arch::X64, Arch::X64, &opts);
+        assert!(!env.path.iter().any(|p| p.starts_with(tree.kits())));
+        assert!(env.libpath.is_empty() && env.vars.is_empty());
+        assert!(env.cl_path().is_some());
+        assert!(env.lib.contains(&tree.kits().join("Lib").join(SDK_VER).join("ucrt").join("x64")));
+    }
+
+    #[test]
+    fn dedup_ignores_case_and_trailing_slash() {
+        let mut env = Env {
+            path: [r"C:\Foo", r"C:\Bar", r"c:\foo\"].map(PathBuf::from).to_vec(),
+            ..Default::default()
+        };
+        env.dedup();
+        assert_eq!(env.path, [PathBuf::from(r"C:\Foo"), PathBuf::from(r"C:\Bar")]);
+    }
+
+    #[test]
+    fn arm64ec_lib_layout() {
+        let root = std::env::temp_dir().join(format!("vcv-arm64ec-{}", std::process::id()));
+        let tools = root.join("VS").join("VC").join("Tools").join("MSVC").join("14.40.33807");
+        let kits = root.join("Kits");
+        let ver = "10.0.22621.0";
+        let ec = Path::new("arm64").join("arm64ec");
+        let vc_lib = tools.join("lib").join(&ec);
+        let um_lib = kits.join("Lib").join(ver).join("um").join(&ec);
+        let ucrt_lib = kits.join("Lib").join(ver).join("ucrt").join(&ec);
+        for dir in [&vc_lib, &um_lib, &ucrt_lib, &tools.join("lib").join("arm64")] {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+
+        let vs = VsInfo {
+            install: root.join("VS"),
+            version: "17.9".into(),
+            vc: root.join("VS").join("VC"),
+            tools_ver: "14.40.33807".into(),
+            tools: tools.clone(),
+        };
+        let sdk = SdkInfo { path: kits.clone(), version: ver.into() };
+        let env = build_env(&vs, Some(&sdk), Some(&sdk), Arch::Arm64, Arch::Arm64ec, &EnvOpts::default());
+        let _ = std::fs::remove_dir_all(&root);
+
+        // ARM64EC libs sit below the arm64 ones, never in plain arm64
+        assert_eq!(env.lib, [vc_lib.clone(), um_lib, ucrt_lib]);
+        assert_eq!(env.libpath, [vc_lib]);
+    }
+}
@@ -0,0 +1,130 @@
+//! # Fixture Module
+//!
+//! Synthetic Visual Studio / Windows Kits trees for unit tests.
+//!
+//! ## Purpose
+//! Creates the same layout `vcv --self-test` uses under a per-test temp
+//! directory, removed again when the `Tree` is dropped. Compiled for tests only.
+//!
+//! ## Key Functions
+//! - `Tree::new(name)` - Empty tree for one test; `dirs()`/`files()` fill it
+//! - `Tree::skeleton(name)` - VS install (`VS`) plus SDK and UCRT (`Kits\10`)
+//! - `Tree::vs()` / `sdk()` / `ucrt()` - Detection results for the skeleton
+//!
+//! ## Dependencies
+//! - `detect` for `vs_from_path`/`sdk_from_root`/`ucrt_from_root`
+
+use crate::detect::{self, SdkInfo, VsInfo};
+use crate::SdkSelect;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const TOOLS_VER: &str = "14.40.33807";
+pub const SDK_VER: &str = "10.0.22621.0";
+pub const REDIST_VER: &str = "14.40.33816";
+
+/// Temp directory holding one test's tree
+pub struct Tree {
+    pub root: PathBuf,
+}
+
+impl Tree {
+    /// Empty tree; `name` keeps parallel tests apart
+    pub fn new(name: &str) -> Tree {
+        let root = std::env::temp_dir().join(format!("vcv-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        Tree { root }
+    }
+
+    /// Create directories (relative to the root)
+    pub fn dirs<P: AsRef<Path>>(&self, dirs: &[P]) {
+        for d in dirs {
+            fs::create_dir_all(self.root.join(d)).unwrap();
+        }
+    }
+
+    /// Create files with their contents, parent directories included
+    pub fn files<P: AsRef<Path>>(&self, files: &[(P, &str)]) {
+        for (f, content) in files {
+            let p = self.root.join(f);
+            if let Some(parent) = p.parent() {
+                fs::create_dir_all(parent).unwrap();
+            }
+            fs::write(p, content).unwrap();
+        }
+    }
+
+    /// VS with x64/x86/arm64 compilers (no `Hostarm64\x86`) and a versioned SDK + UCRT
+    pub fn skeleton(name: &str) -> Tree {
+        let tree = Tree::new(name);
+        let tools = PathBuf::from("VS").join("VC").join("Tools").join("MSVC").join(TOOLS_VER);
+        let aux = PathBuf::from("VS").join("VC").join("Auxiliary").join("Build");
+        let kits = PathBuf::from("Kits").join("10");
+
+        tree.dirs(&[
+            tools.join("include"),
+            tools.join("lib").join("x64"),
+            tools.join("lib").join("x86"),
+            tools.join("lib").join("arm64"),
+            tools.join("ATLMFC").join("include"),
+            tools.join("ATLMFC").join("lib").join("x64"),
+            kits.join("include").join(SDK_VER).join("shared"),
+            kits.join("include").join(SDK_VER).join("winrt"),
+            kits.join("include").join(SDK_VER).join("cppwinrt"),
+            kits.join("include").join(SDK_VER).join("ucrt"),
+            kits.join("Lib").join(SDK_VER).join("um").join("x64"),
+            kits.join("Lib").join(SDK_VER).join("um").join("x86"),
+            kits.join("Lib").join(SDK_VER).join("um").join("arm64"),
+            kits.join("Lib").join(SDK_VER).join("ucrt").join("x86"),
+            kits.join("Lib").join(SDK_VER).join("ucrt").join("arm64"),
+            kits.join("bin").join(SDK_VER).join("x64"),
+            kits.join("bin").join(SDK_VER).join("x86"),
+            kits.join("bin").join(SDK_VER).join("arm64"),
+            kits.join("bin").join("x64"),
+            kits.join("bin").join("arm64"),
+            kits.join("UnionMetadata").join(SDK_VER),
+            kits.join("References").join(SDK_VER),
+            PathBuf::from("VS").join("VC").join("Redist").join("MSVC").join(REDIST_VER),
+        ]);
+        tree.files(&[
+            (aux.join("Microsoft.VCToolsVersion.default.txt"), TOOLS_VER),
+            (aux.join("Microsoft.VCRedistVersion.default.txt"), REDIST_VER),
+            (tools.join("bin").join("Hostx64").join("x64").join("cl.exe"), ""),
+            (tools.join("bin").join("Hostx64").join("x86").join("cl.exe"), ""),
+            (tools.join("bin").join("Hostarm64").join("arm64").join("cl.exe"), ""),
+            (tools.join("bin").join("Hostarm64").join("x64").join("cl.exe"), ""),
+            (kits.join("include").join(SDK_VER).join("um").join("winsdkver.h"), ""),
+            (kits.join("Lib").join(SDK_VER).join("ucrt").join("x64").join("ucrt.lib"), ""),
+        ]);
+        tree
+    }
+
+    /// `VS\VC\Tools\MSVC\<TOOLS_VER>` of the skeleton
+    pub fn tools(&self) -> PathBuf {
+        self.root.join("VS").join("VC").join("Tools").join("MSVC").join(TOOLS_VER)
+    }
+
+    /// `Kits\10` of the skeleton (SDK and UCRT root)
+    pub fn kits(&self) -> PathBuf {
+        self.root.join("Kits").join("10")
+    }
+
+    pub fn vs(&self) -> VsInfo {
+        detect::vs_from_path(&self.root.join("VS"), None).expect("skeleton VS not detected")
+    }
+
+    pub fn sdk(&self) -> SdkInfo {
+        detect::sdk_from_root(&self.kits(), SdkSelect::Newest, None).expect("skeleton SDK not detected")
+    }
+
+    pub fn ucrt(&self) -> SdkInfo {
+        detect::ucrt_from_root(&self.kits(), None).expect("skeleton UCRT not detected")
+    }
+}
+
+impl Drop for Tree {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
@@ -7,135 +7,1002 @@
 //! - PowerShell: `$env:VAR = "value"`
 //! - CMD: `set "VAR=value"`
 //! - Bash/MSYS2: `export VAR="value"` (with path conversion)
-//! - JSON: structured output for tooling
+//! - fish: `set -gx VAR "value"` (with path conversion)
+//! - xonsh: `$VAR = 'value'`, PATH as a list
+//! - csh/tcsh: `setenv VAR "value";` (with path conversion)
+//! - JSON/YAML: structured output for tooling
 //!
 //! ## Key Functions
 //! - `fmt_ps()` - PowerShell format
+//! - `fmt_psm1()` - PowerShell module with Enter-VcEnv/Exit-VcEnv
 //! - `fmt_cmd()` - CMD.exe format
 //! - `fmt_sh()` - Bash/MSYS2 format (converts C:\ to /c/)
-//! - `fmt_json()` - JSON format for programmatic use
+//! - `win_to_unix()` - The path conversion itself (MSYS2, Cygwin or unchanged)
+//! - `fmt_fish()` - fish shell format (PATH as a list)
+//! - `fmt_xonsh()` - xonsh format (PATH as a list)
+//! - `fmt_csh()` - csh/tcsh format (converts C:\ to /c/)
+//! - `fmt_cmake()` - CMake toolchain file (compiler + ENV{INCLUDE}/ENV{LIB})
+//! - `fmt_make()` - GNU Make fragment (`include vcvenv.mk`)
+//! - `fmt_meson()` - Meson native file (`meson setup --native-file vcv.ini`)
+//! - `fmt_cargo()` - `cargo:` directives for a Rust build script to pass through
+//! - `fmt_vscode()` - VS Code `c_cpp_properties.json` (IntelliSense)
+//! - `fmt_github()` - GitHub Actions `$GITHUB_ENV` / `$GITHUB_PATH` files
+//! - `fmt_dotenv()` - `.env` file for direnv/docker/VS Code
+//! - `fmt_plain()` - Bare `KEY=VALUE` lines for custom parsers
+//! - `fmt_json_schema()` - Versioned JSON with VS/SDK metadata for programmatic use
+//! - `fmt_json_line()` - Same document on one line with an `arch` key (`--matrix` NDJSON)
+//! - `fmt_json()` - Legacy flat JSON (`--legacy-json`)
+//! - `fmt_yaml()` - YAML (flat, like the legacy JSON)
 //!
 //! ## Dependencies
 //! - `env::Env` struct with assembled paths
 //! - `serde_json` for JSON serialization
 
+use crate::detect::{SdkInfo, VsInfo};
 use crate::env::Env;
-use std::path::Path;
+use crate::Arch;
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Formatter options
+#[derive(Debug, Clone, Copy)]
+pub struct FmtOpts<'a> {
+    /// Reference the existing variable after the VS paths (`;%PATH%`)
+    pub inherit: bool,
+    /// Drive path style for PATH in sh/fish/csh output
+    pub path_style: PathStyle,
+    /// Convert PATH to Unix style in xonsh output (xonsh under MSYS2/Cygwin)
+    pub unix_paths: bool,
+    /// Put the VS paths after the existing value instead of before (cmd/ps/sh/fish)
+    pub append: bool,
+    /// Prepended to every variable name (`VCV_` -> `VCV_PATH`) in cmd/ps/sh/json output
+    pub prefix: &'a str,
+}
+
+impl Default for FmtOpts<'_> {
+    fn default() -> Self {
+        Self { inherit: true, path_style: PathStyle::Msys, unix_paths: false, append: false, prefix: "" }
+    }
+}
+
+/// How PATH entries are written for Unix-like shells
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PathStyle {
+    /// MSYS2 / Git Bash: `/c/foo`
+    Msys,
+    /// Cygwin: `/cygdrive/c/foo`
+    Cygwin,
+    /// Unchanged `C:\foo`, `;`-separated (native Windows ports of bash/fish)
+    Windows,
+}
+
+impl PathStyle {
+    /// PATH list separator for this style
+    fn separator(self) -> &'static str {
+        match self {
+            PathStyle::Windows => ";",
+            _ => ":",
+        }
+    }
+}
+
+impl FmtOpts<'_> {
+    /// Reference to the existing value, or nothing with `--no-inherit`
+    fn tail<'a>(&self, existing: &'a str) -> &'a str {
+        if self.inherit { existing } else { "" }
+    }
+
+    /// VS paths joined with the existing value: before it by default, after it with `--append`
+    fn merge(&self, value: String, sep: &str, existing: &str) -> String {
+        match (self.inherit, self.append) {
+            (false, _) => value,
+            (true, false) => format!("{}{}{}", value, sep, existing),
+            (true, true) => format!("{}{}{}", existing, sep, value),
+        }
+    }
+}
+
+/// Escape a value for `set "VAR=value"` in a batch file
+/// `%` is doubled; an embedded `"` toggles cmd's quote state, so `^ & | < >`
+/// after an odd number of quotes are caret-escaped (`set` keeps everything up to the last quote)
+fn escape_cmd(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut quoted = true;
+    for c in s.chars() {
+        match c {
+            '%' => out.push_str("%%"),
+            '"' => {
+                quoted = !quoted;
+                out.push(c);
+            }
+            '^' | '&' | '|' | '<' | '>' if !quoted => {
+                out.push('^');
+                out.push(c);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Escape a value for a PowerShell double-quoted string (backtick is the escape char)
+fn escape_ps(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '`' | '"' | '$') {
+            out.push('`');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Escape a value for a POSIX sh double-quoted string
+/// `\` must be doubled too: values like `VSINSTALLDIR` end in `\`, which would escape the closing quote
+fn escape_sh(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '\\' | '"' | '$' | '`') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Escape a value for a fish double-quoted string (only `\`, `"` and `$` are special)
+fn escape_fish(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '\\' | '"' | '$') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
 
 /// Format for cmd.exe
-pub fn fmt_cmd(env: &Env) -> String {
+pub fn fmt_cmd(env: &Env, opts: &FmtOpts) -> String {
+    let mut lines = Vec::new();
+
+    for (name, list) in env.lists() {
+        if !list.is_empty() {
+            let var = format!("{}{}", opts.prefix, name);
+            let paths: Vec<_> = list.iter().map(|p| p.display().to_string()).collect();
+            let value = opts.merge(escape_cmd(&paths.join(";")), ";", &format!("%{}%", var));
+            lines.push(format!("set \"{}={}\"", var, value));
+        }
+    }
+
+    for (k, v) in &env.vars {
+        lines.push(format!("set \"{}{}={}\"", opts.prefix, k, escape_cmd(v)));
+    }
+
+    lines.join("\n")
+}
+
+/// Format for PowerShell
+/// The existing value is read in a `$(...)` subexpression; an unset variable (INCLUDE in a fresh
+/// session) is `$null` even under `Set-StrictMode` and expands to nothing
+pub fn fmt_ps(env: &Env, opts: &FmtOpts) -> String {
+    let mut lines = Vec::new();
+
+    for (name, list) in env.lists() {
+        if !list.is_empty() {
+            let var = format!("{}{}", opts.prefix, name);
+            let paths: Vec<_> = list.iter().map(|p| p.display().to_string()).collect();
+            let value = opts.merge(escape_ps(&paths.join(";")), ";", &format!("$($env:{})", var));
+            lines.push(format!("$env:{} = \"{}\"", var, value));
+        }
+    }
+
+    for (k, v) in &env.vars {
+        lines.push(format!("$env:{}{} = \"{}\"", opts.prefix, k, escape_ps(v)));
+    }
+
+    lines.join("\n")
+}
+
+/// Format as a PowerShell module with `Enter-VcEnv` / `Exit-VcEnv`
+/// Enter saves the prior values of every variable it touches; Exit restores them
+pub fn fmt_psm1(env: &Env, opts: &FmtOpts) -> String {
+    let mut names: Vec<&str> = Vec::new();
+    for (name, list) in [("PATH", &env.path), ("INCLUDE", &env.include), ("LIB", &env.lib), ("LIBPATH", &env.libpath)] {
+        if !list.is_empty() {
+            names.push(name);
+        }
+    }
+    names.extend(env.vars.keys().map(|k| k.as_str()));
+
+    let saved: Vec<_> = names.iter().map(|n| format!("'{}'", n)).collect();
+    let body: Vec<_> = fmt_ps(env, opts).lines().map(|l| format!("    {}", l)).collect();
+
+    let mut lines = vec![
+        "# Visual Studio environment module generated by vcv".to_string(),
+        "$script:VcvSaved = $null".to_string(),
+        String::new(),
+        "function Enter-VcEnv {".to_string(),
+        "    if ($script:VcvSaved) { return }".to_string(),
+        "    $script:VcvSaved = @{}".to_string(),
+        format!("    foreach ($name in @({})) {{", saved.join(", ")),
+        "        $script:VcvSaved[$name] = [Environment]::GetEnvironmentVariable($name, 'Process')".to_string(),
+        "    }".to_string(),
+    ];
+    lines.extend(body);
+    lines.extend([
+        "}".to_string(),
+        String::new(),
+        "function Exit-VcEnv {".to_string(),
+        "    if (-not $script:VcvSaved) { return }".to_string(),
+        "    foreach ($name in $script:VcvSaved.Keys) {".to_string(),
+        "        [Environment]::SetEnvironmentVariable($name, $script:VcvSaved[$name], 'Process')".to_string(),
+        "    }".to_string(),
+        "    $script:VcvSaved = $null".to_string(),
+        "}".to_string(),
+        String::new(),
+        "Export-ModuleMember -Function Enter-VcEnv, Exit-VcEnv".to_string(),
+    ]);
+
+    lines.join("\n")
+}
+
+/// Convert Windows path to MSYS2/Cygwin path
+/// - `C:\foo` -> `/c/foo` (MSYS2), `/cygdrive/c/foo` (Cygwin) or unchanged (Windows)
+/// - `\\server\share\foo` (UNC) -> `//server/share/foo`
+/// - `\\?\` verbatim prefixes are stripped first
+/// - Relative and drive-relative (`C:foo`) paths only get their separators flipped
+/// - Every result is normalized: lowercase drive, no repeated or trailing slashes,
+///   so `C:\Foo\` and `c:/Foo` (and `/c/Foo/`) all give `/c/Foo` and dedup cleanly
+pub fn win_to_unix(p: &Path, style: PathStyle) -> String {
+    let s = p.display().to_string();
+    if style == PathStyle::Windows {
+        return s;
+    }
+    let s = s.strip_prefix(r"\\?\UNC\").map(|rest| format!(r"\\{}", rest)).unwrap_or(s);
+    let s = s.strip_prefix(r"\\?\").map(str::to_string).unwrap_or(s);
+
+    if let Some(unc) = s.strip_prefix(r"\\") {
+        return format!("/{}", collapse_slashes(&format!("/{}", unc.replace('\\', "/"))));
+    }
+
+    let b = s.as_bytes();
+    let has_drive = b.len() >= 3 && b[0].is_ascii_alphabetic() && b[1] == b':' && (b[2] == b'\\' || b[2] == b'/');
+    let converted = if has_drive {
+        let drive = (b[0] as char).to_ascii_lowercase();
+        let prefix = match style {
+            PathStyle::Cygwin => "/cygdrive",
+            _ => "",
+        };
+        format!("{}/{}{}", prefix, drive, s[2..].replace('\\', "/"))
+    } else {
+        s.replace('\\', "/")
+    };
+    collapse_slashes(&converted)
+}
+
+/// `a//b/` -> `a/b` (a lone `/` stays)
+fn collapse_slashes(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if !(c == '/' && out.ends_with('/')) {
+            out.push(c);
+        }
+    }
+    if out.len() > 1 && out.ends_with('/') {
+        out.pop();
+    }
+    out
+}
+
+/// Format for bash/MSYS2
+/// Every value is double-quoted, so spaces and `(x86)` survive as long as the output is
+/// eval'd quoted: `eval "$(vcv -f sh)"` (unquoted, word splitting collapses repeated spaces)
+pub fn fmt_sh(env: &Env, opts: &FmtOpts) -> String {
+    let mut lines = Vec::new();
+
+    for (name, list) in env.lists() {
+        if !list.is_empty() {
+            let var = format!("{}{}", opts.prefix, name);
+            // PATH in the shell's own style; the others are read by MSVC tools and stay Windows paths
+            let (paths, sep): (Vec<_>, _) = if name == "PATH" {
+                (list.iter().map(|p| win_to_unix(p, opts.path_style)).collect(), opts.path_style.separator())
+            } else {
+                (list.iter().map(|p| p.display().to_string()).collect(), ";")
+            };
+            let value = opts.merge(escape_sh(&paths.join(sep)), sep, &format!("${}", var));
+            lines.push(format!("export {}=\"{}\"", var, value));
+        }
+    }
+
+    for (k, v) in &env.vars {
+        lines.push(format!("export {}{}=\"{}\"", opts.prefix, k, escape_sh(v)));
+    }
+
+    lines.join("\n")
+}
+
+/// Escape a value for a csh double-quoted string: `"`, `$` and backtick can't be escaped
+/// inside quotes, so they are closed, escaped outside, and reopened; `!` is history expansion
+fn escape_csh(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' | '$' | '`' => {
+                out.push_str("\"\\");
+                out.push(c);
+                out.push('"');
+            }
+            '!' => out.push_str("\\!"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Format for csh/tcsh (`eval \`vcv -f csh\``)
+/// Lines end with `;` because backtick substitution joins them into one; INCLUDE/LIB/LIBPATH
+/// are defined first when missing, as csh rejects references to undefined variables
+pub fn fmt_csh(env: &Env, opts: &FmtOpts) -> String {
+    let mut lines = Vec::new();
+
+    if !env.path.is_empty() {
+        let paths: Vec<_> = env.path.iter().map(|p| win_to_unix(p, opts.path_style)).collect();
+        let sep = opts.path_style.separator();
+        lines.push(format!("setenv PATH \"{}\";", opts.merge(escape_csh(&paths.join(sep)), sep, "${PATH}")));
+    }
+    for (name, list) in [("INCLUDE", &env.include), ("LIB", &env.lib), ("LIBPATH", &env.libpath)] {
+        if list.is_empty() {
+            continue;
+        }
+        if opts.inherit {
+            lines.push(format!("if ( ! $?{0} ) setenv {0} \"\";", name));
+        }
+        let paths: Vec<_> = list.iter().map(|p| p.display().to_string()).collect();
+        let existing = format!("${{{}}}", name);
+        lines.push(format!("setenv {} \"{}\";", name, opts.merge(escape_csh(&paths.join(";")), ";", &existing)));
+    }
+
+    for (k, v) in &env.vars {
+        lines.push(format!("setenv {} \"{}\";", k, escape_csh(v)));
+    }
+
+    lines.join("\n")
+}
+
+/// Format for fish shell
+/// PATH is a list variable (space-separated), the others stay `;`-joined strings
+pub fn fmt_fish(env: &Env, opts: &FmtOpts) -> String {
     let mut lines = Vec::new();
 
     if !env.path.is_empty() {
-        let paths: Vec<_> = env.path.iter().map(|p| p.display().to_string()).collect();
-        lines.push(format!("set \"PATH={};%PATH%\"", paths.join(";")));
+        let paths: Vec<_> = env.path.iter().map(|p| format!("\"{}\"", escape_fish(&win_to_unix(p, opts.path_style)))).collect();
+        lines.push(format!("set -gx PATH {}", opts.merge(paths.join(" "), " ", "$PATH")));
     }
     if !env.include.is_empty() {
         let paths: Vec<_> = env.include.iter().map(|p| p.display().to_string()).collect();
-        lines.push(format!("set \"INCLUDE={};%INCLUDE%\"", paths.join(";")));
+        lines.push(format!("set -gx INCLUDE \"{}\"", opts.merge(escape_fish(&paths.join(";")), ";", "$INCLUDE")));
     }
     if !env.lib.is_empty() {
         let paths: Vec<_> = env.lib.iter().map(|p| p.display().to_string()).collect();
-        lines.push(format!("set \"LIB={};%LIB%\"", paths.join(";")));
+        lines.push(format!("set -gx LIB \"{}\"", opts.merge(escape_fish(&paths.join(";")), ";", "$LIB")));
     }
     if !env.libpath.is_empty() {
         let paths: Vec<_> = env.libpath.iter().map(|p| p.display().to_string()).collect();
-        lines.push(format!("set \"LIBPATH={};%LIBPATH%\"", paths.join(";")));
+        lines.push(format!("set -gx LIBPATH \"{}\"", opts.merge(escape_fish(&paths.join(";")), ";", "$LIBPATH")));
     }
 
     for (k, v) in &env.vars {
-        lines.push(format!("set \"{}={}\"", k, v));
+        lines.push(format!("set -gx {} \"{}\"", k, escape_fish(v)));
     }
 
     lines.join("\n")
 }
 
-/// Format for PowerShell
-pub fn fmt_ps(env: &Env) -> String {
+/// Quote a value as a Python single-quoted string literal
+fn py_str(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Format for xonsh (`execx($(vcv -f xonsh))`)
+/// PATH is an EnvPath list (`$PATH[0:0] = [...]`), the others stay `;`-joined strings
+pub fn fmt_xonsh(env: &Env, opts: &FmtOpts) -> String {
     let mut lines = Vec::new();
 
     if !env.path.is_empty() {
-        let paths: Vec<_> = env.path.iter().map(|p| p.display().to_string()).collect();
-        lines.push(format!("$env:PATH = \"{};$env:PATH\"", paths.join(";")));
+        let paths: Vec<_> = env
+            .path
+            .iter()
+            .map(|p| if opts.unix_paths { win_to_unix(p, opts.path_style) } else { p.display().to_string() })
+            .map(|p| py_str(&p))
+            .collect();
+        let target = if opts.inherit { "$PATH[0:0]" } else { "$PATH" };
+        lines.push(format!("{} = [{}]", target, paths.join(", ")));
     }
-    if !env.include.is_empty() {
-        let paths: Vec<_> = env.include.iter().map(|p| p.display().to_string()).collect();
-        lines.push(format!("$env:INCLUDE = \"{};$env:INCLUDE\"", paths.join(";")));
+    for (name, list) in [("INCLUDE", &env.include), ("LIB", &env.lib), ("LIBPATH", &env.libpath)] {
+        if list.is_empty() {
+            continue;
+        }
+        let paths: Vec<_> = list.iter().map(|p| p.display().to_string()).collect();
+        let tail = format!(" + ';' + ${{...}}.get('{}', '')", name);
+        lines.push(format!("${} = {}{}", name, py_str(&paths.join(";")), opts.tail(&tail)));
     }
-    if !env.lib.is_empty() {
-        let paths: Vec<_> = env.lib.iter().map(|p| p.display().to_string()).collect();
-        lines.push(format!("$env:LIB = \"{};$env:LIB\"", paths.join(";")));
+
+    for (k, v) in &env.vars {
+        lines.push(format!("${} = {}", k, py_str(v)));
     }
-    if !env.libpath.is_empty() {
-        let paths: Vec<_> = env.libpath.iter().map(|p| p.display().to_string()).collect();
-        lines.push(format!("$env:LIBPATH = \"{};$env:LIBPATH\"", paths.join(";")));
+
+    lines.join("\n")
+}
+
+/// Path with forward slashes (CMake treats `\` as an escape)
+fn cmake_path(p: &Path) -> String {
+    p.display().to_string().replace('\\', "/")
+}
+
+/// Format as a CMake toolchain file (`cmake -DCMAKE_TOOLCHAIN_FILE=vcv.cmake`)
+/// CMake re-reads it for every try_compile: the environment is set once per process
+/// (`ENV{VCV_TOOLCHAIN_APPLIED}`), so PATH doesn't grow with each read.
+/// `CMAKE_SYSTEM_NAME` is only set for cross builds: it makes CMake set `CMAKE_CROSSCOMPILING`,
+/// which would disable try_run on native builds
+pub fn fmt_cmake(env: &Env, host: Arch, target: Arch, opts: &FmtOpts) -> String {
+    let processor = match target {
+        Arch::X64 => "AMD64",
+        Arch::X86 => "x86",
+        Arch::Arm64 => "ARM64",
+        Arch::Arm64ec => "ARM64EC",
+        Arch::Arm => "ARM",
+    };
+
+    let mut lines = vec!["# CMake toolchain file generated by vcv".to_string()];
+    if host != target {
+        lines.push("set(CMAKE_SYSTEM_NAME Windows)".to_string());
+        lines.push(format!("set(CMAKE_SYSTEM_PROCESSOR \"{}\")", processor));
+    }
+
+    if let Some(cl) = env.cl_path() {
+        lines.push(format!("set(CMAKE_C_COMPILER \"{}\")", cmake_path(&cl)));
+        lines.push(format!("set(CMAKE_CXX_COMPILER \"{}\")", cmake_path(&cl)));
+    }
+
+    lines.push("if(NOT DEFINED ENV{VCV_TOOLCHAIN_APPLIED})".to_string());
+    for (name, list) in env.lists() {
+        if !list.is_empty() {
+            let paths: Vec<_> = list.iter().map(|p| cmake_path(p)).collect();
+            let existing = format!(";$ENV{{{}}}", name);
+            lines.push(format!("  set(ENV{{{}}} \"{}{}\")", name, paths.join(";"), opts.tail(&existing)));
+        }
+    }
+    lines.push("  set(ENV{VCV_TOOLCHAIN_APPLIED} 1)".to_string());
+    lines.push("endif()".to_string());
+
+    lines.join("\n")
+}
+
+/// Escape a value for a GNU Make assignment: `$` -> `$$`, `#` -> `\#`, and a trailing
+/// backslash is followed by `$()` (expands to nothing) so it isn't read as a line continuation
+fn escape_make(s: &str) -> String {
+    let s = s.replace('$', "$$").replace('#', "\\#");
+    if s.ends_with('\\') { format!("{}$()", s) } else { s }
+}
+
+/// Format as a GNU Make fragment (`include vcvenv.mk`): `NAME := value` plus `export NAME`
+/// PATH follows `path_style` (`--path-style windows` for a native Windows make)
+pub fn fmt_make(env: &Env, opts: &FmtOpts) -> String {
+    let mut lines = vec!["# GNU Make fragment generated by vcv".to_string()];
+
+    for (name, list) in env.lists() {
+        if list.is_empty() {
+            continue;
+        }
+        let (paths, sep): (Vec<_>, _) = if name == "PATH" {
+            (list.iter().map(|p| win_to_unix(p, opts.path_style)).collect(), opts.path_style.separator())
+        } else {
+            (list.iter().map(|p| p.display().to_string()).collect(), ";")
+        };
+        let existing = format!("$({})", name);
+        lines.push(format!("{} := {}", name, opts.merge(escape_make(&paths.join(sep)), sep, &existing)));
+        lines.push(format!("export {}", name));
     }
 
     for (k, v) in &env.vars {
-        lines.push(format!("$env:{} = \"{}\"", k, v));
+        lines.push(format!("{} := {}", k, escape_make(v)));
+        lines.push(format!("export {}", k));
     }
 
     lines.join("\n")
 }
 
-/// Convert Windows path to MSYS2/bash path
-fn win_to_unix(p: &Path) -> String {
-    let s = p.display().to_string();
-    if s.len() >= 2 && s.chars().nth(1) == Some(':') {
-        let drive = s.chars().next().unwrap().to_lowercase();
-        format!("/{}{}", drive, s[2..].replace('\\', "/"))
-    } else {
-        s.replace('\\', "/")
+/// Quote a value as a Meson string literal (forward slashes, like CMake)
+fn meson_str(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "/").replace('\'', "\\'"))
+}
+
+/// Format as a Meson native file: `[binaries]` from PATH (cl, lib, rc) and
+/// `[built-in options]` with INCLUDE as `/I` and LIB as `/LIBPATH:` arguments
+pub fn fmt_meson(env: &Env) -> String {
+    let mut lines = vec!["# Meson native file generated by vcv".to_string(), "[binaries]".to_string()];
+
+    if let Some(cl) = env.cl_path() {
+        let cl = meson_str(&cl.display().to_string());
+        lines.push(format!("c = {}", cl));
+        lines.push(format!("cpp = {}", cl));
+    }
+    for (key, exe) in [("ar", "lib.exe"), ("windres", "rc.exe")] {
+        if let Some(tool) = env.find_tool(exe) {
+            lines.push(format!("{} = {}", key, meson_str(&tool.display().to_string())));
+        }
+    }
+
+    let args = |prefix: &str, list: &[PathBuf]| -> String {
+        let items: Vec<_> = list.iter().map(|p| meson_str(&format!("{}{}", prefix, p.display()))).collect();
+        format!("[{}]", items.join(", "))
+    };
+    let compile = args("/I", &env.include);
+    let link = args("/LIBPATH:", &env.lib);
+
+    lines.push(String::new());
+    lines.push("[built-in options]".to_string());
+    for lang in ["c", "cpp"] {
+        lines.push(format!("{}_args = {}", lang, compile));
+        lines.push(format!("{}_link_args = {}", lang, link));
     }
+
+    lines.join("\n")
 }
 
-/// Format for bash/MSYS2
-pub fn fmt_sh(env: &Env) -> String {
+/// VS Code C/C++ extension `c_cpp_properties.json` (IntelliSense include paths + compiler)
+pub fn fmt_vscode(env: &Env, target: Arch) -> String {
+    // No ARM64EC IntelliSense mode: it parses ARM64EC code as ARM64
+    let mode = match target {
+        Arch::X64 => "windows-msvc-x64",
+        Arch::X86 => "windows-msvc-x86",
+        Arch::Arm64 | Arch::Arm64ec => "windows-msvc-arm64",
+        Arch::Arm => "windows-msvc-arm",
+    };
+
+    let mut config = serde_json::Map::new();
+    config.insert("name".into(), "vcv".into());
+    config.insert("includePath".into(), env.include.iter().map(|p| p.display().to_string()).collect());
+    if let Some(cl) = env.cl_path() {
+        config.insert("compilerPath".into(), cl.display().to_string().into());
+    }
+    config.insert("intelliSenseMode".into(), mode.into());
+    if let Some(sdk) = env.vars.get("WindowsSDKVersion") {
+        config.insert("windowsSdkVersion".into(), sdk.trim_end_matches('\\').into());
+    }
+
+    let doc = json!({ "configurations": [config], "version": 4 });
+    serde_json::to_string_pretty(&doc).unwrap()
+}
+
+/// Format for GitHub Actions: returns (`$GITHUB_ENV` text, `$GITHUB_PATH` text)
+/// Lists use `KEY<<EOF` heredocs so `;` needs no escaping
+pub fn fmt_github(env: &Env, opts: &FmtOpts) -> (String, String) {
+    let mut env_lines = Vec::new();
+
+    for (name, list) in [("INCLUDE", &env.include), ("LIB", &env.lib), ("LIBPATH", &env.libpath)] {
+        if list.is_empty() {
+            continue;
+        }
+        let mut paths: Vec<_> = list.iter().map(|p| p.display().to_string()).collect();
+        // GITHUB_ENV replaces the variable, so carry the current value over explicitly
+        if opts.inherit
+            && let Ok(existing) = std::env::var(name)
+            && !existing.is_empty()
+        {
+            paths.push(existing);
+        }
+        env_lines.push(format!("{}<<VCV_EOF", name));
+        env_lines.push(paths.join(";"));
+        env_lines.push("VCV_EOF".to_string());
+    }
+
+    for (k, v) in &env.vars {
+        env_lines.push(format!("{}={}", k, v));
+    }
+
+    // The runner prepends each GITHUB_PATH line, so the last line ends up first
+    let path_lines: Vec<_> = env.path.iter().rev().map(|p| p.display().to_string()).collect();
+
+    (env_lines.join("\n"), path_lines.join("\n"))
+}
+
+/// Format as a dotenv file (`KEY=VALUE`, no quoting, no reference to existing values)
+pub fn fmt_dotenv(env: &Env) -> String {
     let mut lines = Vec::new();
 
-    if !env.path.is_empty() {
-        let paths: Vec<_> = env.path.iter().map(|p| win_to_unix(p)).collect();
-        lines.push(format!("export PATH=\"{}:$PATH\"", paths.join(":")));
+    for (name, list) in [("PATH", &env.path), ("INCLUDE", &env.include), ("LIB", &env.lib), ("LIBPATH", &env.libpath)] {
+        if !list.is_empty() {
+            let paths: Vec<_> = list.iter().map(|p| p.display().to_string().trim().to_string()).collect();
+            lines.push(format!("{}={}", name, paths.join(";")));
+        }
     }
+
+    for (k, v) in &env.vars {
+        lines.push(format!("{}={}", k, v.trim()));
+    }
+
+    lines.join("\n")
+}
+
+/// Format as bare `KEY=VALUE` lines: values verbatim, path lists `;`-joined,
+/// no quoting and no reference to existing values (the lowest common denominator)
+pub fn fmt_plain(env: &Env) -> String {
+    let mut lines = Vec::new();
+
+    for (name, list) in env.lists() {
+        if !list.is_empty() {
+            let paths: Vec<_> = list.iter().map(|p| p.display().to_string()).collect();
+            lines.push(format!("{}={}", name, paths.join(";")));
+        }
+    }
+
+    for (k, v) in &env.vars {
+        lines.push(format!("{}={}", k, v));
+    }
+
+    lines.join("\n")
+}
+
+/// Format as Cargo build-script directives: `cargo:rustc-link-search=native=<dir>`
+/// per LIB entry and `cargo:rustc-env=INCLUDE=...`. No PATH: cargo finds cl.exe itself
+pub fn fmt_cargo(env: &Env) -> String {
+    let mut lines: Vec<_> = env
+        .lib
+        .iter()
+        .map(|p| format!("cargo:rustc-link-search=native={}", p.display()))
+        .collect();
+
     if !env.include.is_empty() {
         let paths: Vec<_> = env.include.iter().map(|p| p.display().to_string()).collect();
-        lines.push(format!("export INCLUDE=\"{};$INCLUDE\"", paths.join(";")));
+        lines.push(format!("cargo:rustc-env=INCLUDE={}", paths.join(";")));
     }
-    if !env.lib.is_empty() {
-        let paths: Vec<_> = env.lib.iter().map(|p| p.display().to_string()).collect();
-        lines.push(format!("export LIB=\"{};$LIB\"", paths.join(";")));
-    }
-    if !env.libpath.is_empty() {
-        let paths: Vec<_> = env.libpath.iter().map(|p| p.display().to_string()).collect();
-        lines.push(format!("export LIBPATH=\"{};$LIBPATH\"", paths.join(";")));
+
+    lines.join("\n")
+}
+
+/// YAML scalar, single-quoted when plain style would be invalid or change its type
+/// (`:`/`#`, leading indicator characters, numbers like "14.40", booleans, null)
+fn yaml_str(s: &str) -> String {
+    let plain = !s.is_empty()
+        && !s.contains([':', '#'])
+        && !s.starts_with(['-', '?', ',', '[', ']', '{', '}', '&', '*', '!', '|', '>', '\'', '"', '%', '@', '`', ' '])
+        && !s.ends_with(' ')
+        && s.parse::<f64>().is_err()
+        && !matches!(s.to_ascii_lowercase().as_str(), "true" | "false" | "yes" | "no" | "on" | "off" | "null" | "~");
+    if plain { s.to_string() } else { format!("'{}'", s.replace('\'', "''")) }
+}
+
+/// Format as YAML (same flat shape as `fmt_json`: path sequences and vars side by side)
+pub fn fmt_yaml(env: &Env) -> String {
+    let mut lines = Vec::new();
+
+    for (name, list) in env.lists() {
+        if list.is_empty() {
+            lines.push(format!("{}: []", name));
+            continue;
+        }
+        lines.push(format!("{}:", name));
+        for p in list {
+            lines.push(format!("  - {}", yaml_str(&p.display().to_string())));
+        }
     }
 
     for (k, v) in &env.vars {
-        lines.push(format!("export {}=\"{}\"", k, v));
+        lines.push(format!("{}: {}", yaml_str(k), yaml_str(v)));
     }
 
     lines.join("\n")
 }
 
-/// Format as JSON
-pub fn fmt_json(env: &Env) -> String {
-    let mut map = serde_json::Map::new();
+/// Version of the `fmt_json_schema` document layout, bumped on incompatible changes
+pub const JSON_SCHEMA: u32 = 1;
+
+#[derive(Serialize)]
+struct JsonDoc<'a> {
+    schema: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    arch: Option<&'a str>,
+    triple: &'a str,
+    vs: JsonVs<'a>,
+    sdk: Option<JsonKit<'a>>,
+    ucrt: Option<JsonKit<'a>>,
+    warnings: &'a [&'a str],
+    env: JsonEnv<'a>,
+}
 
-    let path_arr: Vec<_> = env.path.iter().map(|p| serde_json::Value::String(p.display().to_string())).collect();
-    map.insert("PATH".into(), serde_json::Value::Array(path_arr));
+#[derive(Serialize)]
+struct JsonVs<'a> {
+    version: &'a str,
+    tools_ver: &'a str,
+    install: &'a Path,
+}
 
-    let inc_arr: Vec<_> = env.include.iter().map(|p| serde_json::Value::String(p.display().to_string())).collect();
-    map.insert("INCLUDE".into(), serde_json::Value::Array(inc_arr));
+#[derive(Serialize)]
+struct JsonKit<'a> {
+    version: &'a str,
+    path: &'a Path,
+}
+
+impl<'a> From<&'a SdkInfo> for JsonKit<'a> {
+    fn from(k: &'a SdkInfo) -> Self {
+        Self { version: &k.version, path: &k.path }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonEnv<'a> {
+    #[serde(rename = "PATH")]
+    path: &'a [PathBuf],
+    #[serde(rename = "INCLUDE")]
+    include: &'a [PathBuf],
+    #[serde(rename = "LIB")]
+    lib: &'a [PathBuf],
+    #[serde(rename = "LIBPATH")]
+    libpath: &'a [PathBuf],
+    vars: &'a BTreeMap<String, String>,
+}
+
+/// JSON document with schema version, detection metadata and the environment:
+/// `{"schema": 1, "triple": "...", "vs": {...}, "sdk": {...}, "ucrt": {...}, "warnings": [...], "env": {...}}`
+/// `warnings` holds machine-readable codes such as `"sdk_not_found"` (empty when all is well)
+/// Pretty-printed, or on a single line with `compact`
+pub fn fmt_json_schema(
+    env: &Env,
+    target: Arch,
+    vs: &VsInfo,
+    sdk: Option<&SdkInfo>,
+    ucrt: Option<&SdkInfo>,
+    warnings: &[&str],
+    compact: bool,
+) -> String {
+    to_json(&json_doc(env, target, false, vs, sdk, ucrt, warnings), compact)
+}
+
+fn to_json<T: Serialize>(value: &T, compact: bool) -> String {
+    if compact {
+        serde_json::to_string(value).unwrap()
+    } else {
+        serde_json::to_string_pretty(value).unwrap()
+    }
+}
+
+/// Same document on a single line, tagged with the target arch (one NDJSON record of `--matrix`)
+pub fn fmt_json_line(
+    env: &Env,
+    target: Arch,
+    vs: &VsInfo,
+    sdk: Option<&SdkInfo>,
+    ucrt: Option<&SdkInfo>,
+    warnings: &[&str],
+) -> String {
+    serde_json::to_string(&json_doc(env, target, true, vs, sdk, ucrt, warnings)).unwrap()
+}
+
+fn json_doc<'a>(
+    env: &'a Env,
+    target: Arch,
+    tag_arch: bool,
+    vs: &'a VsInfo,
+    sdk: Option<&'a SdkInfo>,
+    ucrt: Option<&'a SdkInfo>,
+    warnings: &'a [&'a str],
+) -> JsonDoc<'a> {
+    JsonDoc {
+        schema: JSON_SCHEMA,
+        arch: tag_arch.then(|| target.as_str()),
+        triple: target.llvm_triple(),
+        vs: JsonVs { version: &vs.version, tools_ver: &vs.tools_ver, install: &vs.install },
+        sdk: sdk.map(JsonKit::from),
+        ucrt: ucrt.map(JsonKit::from),
+        warnings,
+        env: JsonEnv {
+            path: &env.path,
+            include: &env.include,
+            lib: &env.lib,
+            libpath: &env.libpath,
+            vars: &env.vars,
+        },
+    }
+}
 
-    let lib_arr: Vec<_> = env.lib.iter().map(|p| serde_json::Value::String(p.display().to_string())).collect();
-    map.insert("LIB".into(), serde_json::Value::Array(lib_arr));
+/// Format as flat JSON (legacy shape: path arrays and vars side by side), single-line with `compact`
+/// Every key gets `prefix` (empty for the plain variable names)
+pub fn fmt_json(env: &Env, prefix: &str, compact: bool) -> String {
+    let mut map = serde_json::Map::new();
 
-    let libpath_arr: Vec<_> = env.libpath.iter().map(|p| serde_json::Value::String(p.display().to_string())).collect();
-    map.insert("LIBPATH".into(), serde_json::Value::Array(libpath_arr));
+    for (name, list) in env.lists() {
+        let arr: Vec<_> = list.iter().map(|p| serde_json::Value::String(p.display().to_string())).collect();
+        map.insert(format!("{}{}", prefix, name), serde_json::Value::Array(arr));
+    }
 
     for (k, v) in &env.vars {
-        map.insert(k.clone(), serde_json::Value::String(v.clone()));
+        map.insert(format!("{}{}", prefix, k), serde_json::Value::String(v.clone()));
+    }
+
+    to_json(&serde_json::Value::Object(map), compact)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env::{build_env, EnvOpts};
+    use crate::fixture::{Tree, SDK_VER};
+
+    /// One PATH and one INCLUDE entry plus a plain variable
+    fn sample_env() -> Env {
+        let mut env = Env {
+            path: vec![PathBuf::from(r"C:\VS\bin")],
+            include: vec![PathBuf::from(r"C:\VS\include")],
+            ..Default::default()
+        };
+        env.vars.insert("VCToolsVersion".into(), "14.40.33807".into());
+        env
+    }
+
+    #[test]
+    fn prefix_renames_variables() {
+        // The prefixed variable and its own reference are renamed, never the real PATH
+        let env = sample_env();
+        let opts = FmtOpts { prefix: "VCV_", ..Default::default() };
+        assert_eq!(
+            fmt_cmd(&env, &opts),
+            "set \"VCV_PATH=C:\\VS\\bin;%VCV_PATH%\"\n\
+             set \"VCV_INCLUDE=C:\\VS\\include;%VCV_INCLUDE%\"\n\
+             set \"VCV_VCToolsVersion=14.40.33807\""
+        );
+        assert_eq!(
+            fmt_ps(&env, &opts),
+            "$env:VCV_PATH = \"C:\\VS\\bin;$($env:VCV_PATH)\"\n\
+             $env:VCV_INCLUDE = \"C:\\VS\\include;$($env:VCV_INCLUDE)\"\n\
+             $env:VCV_VCToolsVersion = \"14.40.33807\""
+        );
+        assert_eq!(
+            fmt_sh(&env, &opts),
+            "export VCV_PATH=\"/c/VS/bin:$VCV_PATH\"\n\
+             export VCV_INCLUDE=\"C:\\\\VS\\\\include;$VCV_INCLUDE\"\n\
+             export VCV_VCToolsVersion=\"14.40.33807\""
+        );
+    }
+
+    /// Output for a single variable `X` set to `value`
+    fn one_var(fmt: fn(&Env, &FmtOpts) -> String, value: &str) -> String {
+        let mut env = Env::default();
+        env.vars.insert("X".into(), value.into());
+        fmt(&env, &FmtOpts::default())
+    }
+
+    #[test]
+    fn escaping_per_shell() {
+        let value = r#"a%b^c`d$e"f^g"#;
+        // cmd: `%` doubled everywhere, `^` escaped only after an odd number of quotes
+        assert_eq!(one_var(fmt_cmd, value), r#"set "X=a%%b^c`d$e"f^^g""#);
+        assert_eq!(one_var(fmt_ps, value), r#"$env:X = "a%b^c``d`$e`"f^g""#);
+        assert_eq!(one_var(fmt_sh, value), r#"export X="a%b^c\`d\$e\"f^g""#);
+    }
+
+    #[test]
+    fn ps_reads_existing_value_in_subexpression() {
+        let env = sample_env();
+        let ps = fmt_ps(&env, &FmtOpts::default());
+        assert!(ps.contains(r#"$env:PATH = "C:\VS\bin;$($env:PATH)""#));
+        assert!(ps.contains(r#"$env:INCLUDE = "C:\VS\include;$($env:INCLUDE)""#));
+        let appended = fmt_ps(&env, &FmtOpts { append: true, ..Default::default() });
+        assert!(appended.contains(r#"$env:PATH = "$($env:PATH);C:\VS\bin""#));
     }
 
-    serde_json::to_string_pretty(&serde_json::Value::Object(map)).unwrap()
+    #[test]
+    fn sh_quotes_paths_with_spaces() {
+        let kits = r"C:\Program Files (x86)\Windows Kits\10";
+        let env = Env {
+            path: vec![PathBuf::from(format!(r"{}\bin", kits))],
+            include: vec![PathBuf::from(format!(r"{}\Include", kits))],
+            ..Default::default()
+        };
+        let opts = FmtOpts { inherit: false, ..Default::default() };
+        assert_eq!(
+            fmt_sh(&env, &opts),
+            "export PATH=\"/c/Program Files (x86)/Windows Kits/10/bin\"\n\
+             export INCLUDE=\"C:\\\\Program Files (x86)\\\\Windows Kits\\\\10\\\\Include\""
+        );
+    }
+
+    #[test]
+    fn windows_path_style_passes_through() {
+        let env = Env {
+            path: [r"C:\Program Files\VS\bin", r"\\nas\libs"].map(PathBuf::from).to_vec(),
+            ..Default::default()
+        };
+        let opts = FmtOpts { inherit: false, path_style: PathStyle::Windows, ..Default::default() };
+        assert_eq!(fmt_sh(&env, &opts), r#"export PATH="C:\\Program Files\\VS\\bin;\\\\nas\\libs""#);
+        assert_eq!(
+            fmt_make(&env, &opts),
+            "# GNU Make fragment generated by vcv\n\
+             PATH := C:\\Program Files\\VS\\bin;\\\\nas\\libs\n\
+             export PATH"
+        );
+    }
+
+    #[test]
+    fn cmake_sets_environment_once() {
+        let cmake = fmt_cmake(&sample_env(), Arch::X64, Arch::X64, &FmtOpts::default());
+        assert!(cmake.ends_with(
+            "if(NOT DEFINED ENV{VCV_TOOLCHAIN_APPLIED})\n  \
+             set(ENV{PATH} \"C:/VS/bin;$ENV{PATH}\")\n  \
+             set(ENV{INCLUDE} \"C:/VS/include;$ENV{INCLUDE}\")\n  \
+             set(ENV{VCV_TOOLCHAIN_APPLIED} 1)\n\
+             endif()"
+        ));
+    }
+
+    #[test]
+    fn cmake_system_only_when_cross() {
+        let native = fmt_cmake(&sample_env(), Arch::X64, Arch::X64, &FmtOpts::default());
+        assert!(!native.contains("CMAKE_SYSTEM_NAME") && !native.contains("CMAKE_SYSTEM_PROCESSOR"));
+        let cross = fmt_cmake(&sample_env(), Arch::X64, Arch::Arm64, &FmtOpts::default());
+        assert!(cross.contains("set(CMAKE_SYSTEM_NAME Windows)\nset(CMAKE_SYSTEM_PROCESSOR \"ARM64\")"));
+    }
+
+    /// json and sh output for the skeleton plus extra SDK/toolset versions created in `order`,
+    /// with the tree's root cut from every path so different trees compare equal
+    fn detected_output(name: &str, order: &[&str]) -> (String, String) {
+        let tree = Tree::skeleton(name);
+        let msvc = tree.tools().parent().unwrap().to_path_buf();
+        for ver in order {
+            tree.files(&[
+                (msvc.join(format!("14.{}", ver)).join("bin").join("Hostx64").join("x64").join("cl.exe"), ""),
+                (tree.kits().join("include").join(format!("10.0.{}.0", ver)).join("um").join("winsdkver.h"), ""),
+                (tree.kits().join("Lib").join(format!("10.0.{}.0", ver)).join("ucrt").join("x64").join("ucrt.lib"), ""),
+            ]);
+        }
+        let sdk = crate::detect::sdk_from_root(&tree.kits(), crate::SdkSelect::Newest, None).unwrap();
+        let ucrt = crate::detect::ucrt_from_root(&tree.kits(), None).unwrap();
+        let env = build_env(&tree.vs(), Some(&sdk), Some(&ucrt), Arch::X64, Arch::X64, &EnvOpts::default());
+
+        let root = tree.root.display().to_string();
+        let strip = |list: &Vec<PathBuf>| -> Vec<PathBuf> {
+            list.iter().map(|p| p.strip_prefix(&tree.root).unwrap().to_path_buf()).collect()
+        };
+        let rel = Env {
+            path: strip(&env.path),
+            include: strip(&env.include),
+            lib: strip(&env.lib),
+            libpath: strip(&env.libpath),
+            vars: env.vars.iter().map(|(k, v)| (k.clone(), v.replace(&root, ""))).collect(),
+            ..Default::default()
+        };
+        (fmt_json(&rel, "", false), fmt_sh(&rel, &FmtOpts::default()))
+    }
+
+    #[test]
+    fn output_independent_of_creation_order() {
+        let first = detected_output("order-a", &["20348", "19041", "26100"]);
+        let second = detected_output("order-b", &["26100", "19041", "20348"]);
+        assert_eq!(first, second);
+        // The newest extra SDK wins over the skeleton's
+        assert!(first.0.contains("10.0.26100.0") && !first.0.contains(SDK_VER));
+    }
+
+    #[test]
+    fn win_to_unix_normalizes() {
+        // Drive case, separators and trailing slashes normalized
+        let cases = [
+            (r"C:\Foo\", PathStyle::Msys, "/c/Foo"),
+            ("c:/Foo", PathStyle::Msys, "/c/Foo"),
+            (r"D:\a\\b", PathStyle::Cygwin, "/cygdrive/d/a/b"),
+            ("/c/Foo/", PathStyle::Msys, "/c/Foo"),
+            (r"\\server\share\", PathStyle::Msys, "//server/share"),
+            (r"\\nas\libs", PathStyle::Cygwin, "//nas/libs"),
+            (r"C:\Program Files", PathStyle::Msys, "/c/Program Files"),
+            (r"C:\Program Files", PathStyle::Cygwin, "/cygdrive/c/Program Files"),
+            (r"foo\bar", PathStyle::Msys, "foo/bar"),
+        ];
+        for (input, style, expected) in cases {
+            assert_eq!(win_to_unix(Path::new(input), style), expected, "{}", input);
+        }
+    }
 }
@@ -0,0 +1,131 @@
+//! # Shell Init Module
+//!
+//! Startup snippets that apply the VS environment in every new shell.
+//!
+//! ## Purpose
+//! Prints (or writes, with `--write`) a guarded block for the shell's profile:
+//! `# >>> vcv >>>` ... `# <<< vcv <<<`. Writing replaces an existing block in place,
+//! so re-running `--install` updates it instead of adding a second copy.
+//!
+//! ## Key Functions
+//! - `run(shell, write, quiet)` - Print or install the snippet for one shell
+//!
+//! ## Profile Files
+//! - PowerShell: `$PROFILE` (as reported by pwsh, else Windows PowerShell)
+//! - bash: `~/.bashrc`, fish: `~/.config/fish/config.fish`, xonsh: `~/.xonshrc`, csh: `~/.cshrc`
+//! - cmd: `%LOCALAPPDATA%\vcv\autorun.cmd`, registered as the cmd.exe `AutoRun`
+//!
+//! ## Dependencies
+//! - `env::add_cmd_autorun` for the cmd.exe registry hook
+
+use crate::Format;
+use clap::ValueEnum;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use vcv_rs::env;
+
+const BEGIN: &str = ">>> vcv >>>";
+const END: &str = "<<< vcv <<<";
+
+/// Home directory (`HOME` under MSYS2/Cygwin, else `USERPROFILE`)
+fn home() -> Option<PathBuf> {
+    std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE")).map(PathBuf::from)
+}
+
+/// `$PROFILE` of PowerShell 7, falling back to Windows PowerShell 5.1
+fn ps_profile() -> Option<PathBuf> {
+    ["pwsh", "powershell"].into_iter().find_map(|exe| {
+        let out = Command::new(exe).args(["-NoLogo", "-NoProfile", "-Command", "$PROFILE"]).output().ok()?;
+        let path = String::from_utf8_lossy(&out.stdout).trim().to_string();
+        (out.status.success() && !path.is_empty()).then(|| PathBuf::from(path))
+    })
+}
+
+/// Profile file, comment leader and startup lines for `shell`
+fn snippet(shell: Format, exe: &str) -> Result<(Option<PathBuf>, &'static str, String), String> {
+    let in_home = |rel: &[&str]| home().map(|h| rel.iter().fold(h, |p, r| p.join(r)));
+    Ok(match shell {
+        Format::Ps | Format::Powershell => (ps_profile(), "#", format!("{} -q -f ps | Invoke-Expression", exe)),
+        Format::Sh | Format::Bash => (in_home(&[".bashrc"]), "#", format!("eval \"$({} -q -f sh)\"", exe)),
+        Format::Fish => (in_home(&[".config", "fish", "config.fish"]), "#", format!("{} -q -f fish | source", exe)),
+        Format::Xonsh => (in_home(&[".xonshrc"]), "#", format!("execx($({} -q -f xonsh))", exe)),
+        Format::Csh => (in_home(&[".cshrc"]), "#", format!("eval `{} -q -f csh`", exe)),
+        // The for /f child cmd.exe runs AutoRun too: VCV_INIT stops the recursion
+        Format::Cmd => (
+            std::env::var_os("LOCALAPPDATA").map(|d| PathBuf::from(d).join("vcv").join("autorun.cmd")),
+            "@rem",
+            format!(
+                "@if defined VCV_INIT exit /b\n@set VCV_INIT=1\n@for /f \"delims=\" %%i in ('{} -q -f cmd') do @%%i",
+                exe
+            ),
+        ),
+        other => {
+            let name = other.to_possible_value().map(|v| v.get_name().to_string()).unwrap_or_default();
+            return Err(format!("--install supports ps, cmd, sh, fish, xonsh and csh, not {}", name));
+        }
+    })
+}
+
+/// `text` with the guarded block replaced, or appended when there is none
+fn with_block(text: &str, block: &str, comment: &str) -> String {
+    let begin = format!("{} {}", comment, BEGIN);
+    let end = format!("{} {}", comment, END);
+    if let Some(start) = text.find(&begin)
+        && let Some(stop) = text[start..].find(&end)
+    {
+        let rest = text[start + stop + end.len()..].trim_start_matches(['\r', '\n']);
+        return format!("{}{}{}", &text[..start], block, rest);
+    }
+    let sep = if text.is_empty() || text.ends_with('\n') { "" } else { "\n" };
+    format!("{}{}{}", text, sep, block)
+}
+
+fn write_block(file: &Path, block: &str, comment: &str) -> Result<(), String> {
+    let text = std::fs::read_to_string(file).unwrap_or_default();
+    if let Some(dir) = file.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+    }
+    std::fs::write(file, with_block(&text, block, comment)).map_err(|e| format!("Cannot write {}: {}", file.display(), e))
+}
+
+/// Print the init block for `shell`, or write it into the profile with `write`
+/// Exits with an error for non-shell formats or when the profile can't be located/written
+pub fn run(shell: Format, write: bool, quiet: bool) {
+    let exe = std::env::current_exe()
+        .ok()
+        .and_then(|p| p.file_stem().map(|s| s.to_string_lossy().to_string()))
+        .unwrap_or_else(|| "vcv".into());
+
+    let (file, comment, lines) = snippet(shell, &exe).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+    let block = format!("{c} {}\n{}\n{c} {}\n", BEGIN, lines, END, c = comment);
+
+    if !write {
+        if !quiet {
+            match &file {
+                Some(f) => eprintln!("# Add to {} (or rerun with --write):", f.display()),
+                None => eprintln!("# Add to your shell profile:"),
+            }
+        }
+        print!("{}", block);
+        return;
+    }
+
+    let Some(file) = file else {
+        eprintln!("Error: Cannot locate the profile file for this shell");
+        std::process::exit(1);
+    };
+    let mut result = write_block(&file, &block, comment);
+    if matches!(shell, Format::Cmd) {
+        result = result.and_then(|_| env::add_cmd_autorun(&file));
+    }
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+    if !quiet {
+        eprintln!("# Installed in {} (new shells will pick it up)", file.display());
+    }
+}
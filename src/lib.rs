@@ -0,0 +1,205 @@
+//! # vcv_rs - Fast Visual Studio Environment Setup (library)
+//!
+//! Detects Visual Studio, Windows SDK and UCRT and assembles the MSVC build
+//! environment (PATH, INCLUDE, LIB, LIBPATH + standard variables) without
+//! running vcvars64.bat. The `vcv` binary is a thin CLI on top of this crate.
+//!
+//! ## Usage
+//! ```no_run
+//! use vcv_rs::{setup, Arch, SetupOpts};
+//!
+//! let opts = SetupOpts { target: Arch::Arm64, ..Default::default() };
+//! if let Some(env) = setup(&opts) {
+//!     for dir in &env.include {
+//!         println!("{}", dir.display());
+//!     }
+//! }
+//! ```
+//!
+//! ## Modules
+//! - `detect` - VS/SDK/UCRT detection via vswhere and registry
+//! - `env` - Environment variable assembly
+//! - `format` - Output formatters (ps, cmd, sh, json, ...)
+//! - `registry` - Windows registry helpers (internal)
+//!
+//! ## Stability
+//! Items re-exported at the crate root (`setup`, `SetupOpts`, `detect_vs`,
+//! `detect_sdk`, `detect_ucrt`, `build_env`, `Env`, `EnvOpts`, `VsInfo`,
+//! `SdkInfo`, `Arch`, `SdkSelect`, `Product`) are the public API and follow semver.
+//! New fields may be added to `SetupOpts`/`EnvOpts` (construct them with
+//! `..Default::default()`). Other items in the public modules are usable but
+//! may change between minor versions.
+//!
+//! ## Dependencies
+//! - `winreg` - Windows registry access
+//! - `serde_json` - JSON parsing (vswhere output) and JSON output
+//! - `clap` - `ValueEnum` for `Arch`/`SdkSelect`/`Product`/`PersistScope`
+
+pub mod detect;
+pub mod env;
+pub mod format;
+mod registry;
+#[cfg(test)]
+mod fixture;
+
+pub use detect::{detect_sdk, detect_ucrt, detect_vs, SdkInfo, VsInfo};
+pub use env::{build_env, Env, EnvOpts};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Host/target architecture
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Arch {
+    X64,
+    X86,
+    Arm64,
+    Arm64ec,
+    Arm,
+}
+
+impl Arch {
+    /// Arch name as used in toolset/SDK directories and `Platform`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Arch::X64 => "x64",
+            Arch::X86 => "x86",
+            Arch::Arm64 => "arm64",
+            Arch::Arm64ec => "arm64ec",
+            Arch::Arm => "arm",
+        }
+    }
+
+    /// Compiler directory under `bin\Host<arch>` (ARM64EC is built by the arm64 compiler)
+    pub fn bin_dir(&self) -> &'static str {
+        match self {
+            Arch::Arm64ec => "arm64",
+            other => other.as_str(),
+        }
+    }
+
+    /// Library directory relative to a `lib` root (ARM64EC libs live in `arm64\arm64ec`)
+    pub fn lib_dir(&self) -> PathBuf {
+        match self {
+            Arch::Arm64ec => PathBuf::from("arm64").join("arm64ec"),
+            other => PathBuf::from(other.as_str()),
+        }
+    }
+
+    /// LLVM target triple for clang-cl `--target`
+    pub fn llvm_triple(&self) -> &'static str {
+        match self {
+            Arch::X64 => "x86_64-pc-windows-msvc",
+            Arch::X86 => "i686-pc-windows-msvc",
+            Arch::Arm64 => "aarch64-pc-windows-msvc",
+            Arch::Arm64ec => "arm64ec-pc-windows-msvc",
+            Arch::Arm => "thumbv7a-pc-windows-msvc",
+        }
+    }
+
+    /// Toolset host directory name (`bin\Host<arch>`)
+    pub fn host_dir(&self) -> &'static str {
+        match self {
+            Arch::X64 => "Hostx64",
+            Arch::X86 => "Hostx86",
+            Arch::Arm64 => "Hostarm64",
+            // No ARM64EC/ARM32-hosted toolsets exist; the CLI rejects them as host
+            Arch::Arm64ec => "Hostarm64",
+            Arch::Arm => "Hostarm",
+        }
+    }
+}
+
+/// Visual Studio product (edition) to restrict detection to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Product {
+    Community,
+    Professional,
+    Enterprise,
+    Buildtools,
+}
+
+impl Product {
+    /// vswhere `-products` identifier
+    pub fn id(self) -> &'static str {
+        match self {
+            Product::Community => "Microsoft.VisualStudio.Product.Community",
+            Product::Professional => "Microsoft.VisualStudio.Product.Professional",
+            Product::Enterprise => "Microsoft.VisualStudio.Product.Enterprise",
+            Product::Buildtools => "Microsoft.VisualStudio.Product.BuildTools",
+        }
+    }
+
+    /// Display name as vswhere reports it (e.g. "BuildTools")
+    pub fn name(self) -> &'static str {
+        self.id().trim_start_matches("Microsoft.VisualStudio.Product.")
+    }
+}
+
+/// Where `env::persist` writes the environment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PersistScope {
+    /// `HKCU\Environment`
+    User,
+    /// `HKLM\SYSTEM\CurrentControlSet\Control\Session Manager\Environment` (needs elevation)
+    Machine,
+}
+
+/// Which Windows SDK to pick when several are installed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SdkSelect {
+    Newest,
+    Oldest,
+}
+
+/// Options for `setup()`
+#[derive(Debug, Clone)]
+pub struct SetupOpts {
+    pub host: Arch,
+    pub target: Arch,
+    /// VS version year (2017, 2019, 2022); None = latest
+    pub vs_year: Option<u16>,
+    /// MSVC toolset version; None = VS default
+    pub toolset: Option<String>,
+    /// Windows SDK version; None = pick per `sdk_select`
+    pub sdk: Option<String>,
+    pub sdk_select: SdkSelect,
+    /// UCRT version; None = newest
+    pub ucrt: Option<String>,
+    /// vswhere.exe location
+    pub vswhere: PathBuf,
+    /// Consider VS Preview installs too
+    pub prerelease: bool,
+    /// Only this VS product; None = any
+    pub product: Option<Product>,
+    pub env: EnvOpts,
+}
+
+impl Default for SetupOpts {
+    fn default() -> Self {
+        Self {
+            host: Arch::X64,
+            target: Arch::X64,
+            vs_year: None,
+            toolset: None,
+            sdk: None,
+            sdk_select: SdkSelect::Newest,
+            ucrt: None,
+            vswhere: PathBuf::from(detect::DEFAULT_VSWHERE),
+            prerelease: false,
+            product: None,
+            env: EnvOpts::default(),
+        }
+    }
+}
+
+/// Detect VS/SDK/UCRT and assemble the environment in one call
+/// Returns None when no matching Visual Studio installation is found
+pub fn setup(opts: &SetupOpts) -> Option<Env> {
+    let vs = detect_vs(&opts.vswhere, opts.prerelease, opts.product, opts.vs_year, opts.toolset.as_deref())?;
+    let sdk = detect_sdk(opts.sdk_select, opts.sdk.as_deref());
+    let ucrt = detect_ucrt(opts.ucrt.as_deref());
+    Some(build_env(&vs, sdk.as_ref(), ucrt.as_ref(), opts.host, opts.target, &opts.env))
+}
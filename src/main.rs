@@ -13,39 +13,55 @@
 //! ```
 //!
 //! ## Modules
-//! - `detect` - VS/SDK/UCRT detection via vswhere and registry
-//! - `env` - Environment variable assembly
-//! - `format` - Output formatters (ps, cmd, sh, json)
-//! - `registry` - Windows registry helpers
+//! - `cache` - Detection results cache (`--cache`)
+//! - `color` - ANSI colors for stderr info and warnings (`--color`)
+//! - `install` - Shell profile snippets (`--install`)
+//! - `config` - Defaults from `vcv.toml` / `%APPDATA%\vcv\config.toml`
+//! - `selftest` - Built-in check against a synthetic VS/SDK layout
+//! - `snapshot` - Save/replay a fully resolved environment
+//! - `verify` - Compare with the real vcvarsall.bat (`--verify`)
+//!
+//! Detection, assembly and formatting live in the `vcv_rs` library (`lib.rs`).
 //!
 //! ## Dependencies
+//! - `vcv_rs` - Library API (detect, env, format)
 //! - `clap` - CLI argument parsing
-//! - `winreg` - Windows registry access
-//! - `serde_json` - JSON parsing (vswhere output)
+//! - `toml` - Config files
 
-mod detect;
-mod env;
-mod format;
-mod registry;
+mod cache;
+mod color;
+mod config;
+mod install;
+mod selftest;
+mod snapshot;
+mod verify;
 
-use clap::{Parser, ValueEnum};
+use cache::CacheEntry;
+use clap::{CommandFactory, FromArgMatches, Parser, ValueEnum};
+use serde::Serialize;
+use snapshot::Snapshot;
 use std::env as std_env;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use vcv_rs::{detect, env, format, Arch, PersistScope, Product, SdkSelect};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
-pub enum Arch {
-    X64,
-    X86,
-    Arm64,
-}
-
-impl Arch {
-    pub fn as_str(&self) -> &'static str {
-        match self {
-            Arch::X64 => "x64",
-            Arch::X86 => "x86",
-            Arch::Arm64 => "arm64",
-        }
-    }
+/// Parse a legacy vcvarsall argument (`amd64`, `x86_amd64`, `x64_arm64`, ...) into (host, target)
+/// A single arch means native (host == target)
+fn parse_vcvars_arg(s: &str) -> Result<(Arch, Arch), String> {
+    let arch = |a: &str| match a.to_ascii_lowercase().as_str() {
+        "x86" => Some(Arch::X86),
+        "amd64" | "x64" => Some(Arch::X64),
+        "arm64" => Some(Arch::Arm64),
+        "arm" => Some(Arch::Arm),
+        _ => None,
+    };
+    let parsed = match s.split_once('_') {
+        Some((host, target)) => arch(host).zip(arch(target)),
+        None => arch(s).map(|a| (a, a)),
+    };
+    parsed.ok_or_else(|| {
+        format!("unrecognized vcvars argument '{}' (expected e.g. x86, amd64, x86_amd64, amd64_arm64)", s)
+    })
 }
 
 #[derive(Debug, Clone, Copy, ValueEnum)]
@@ -56,23 +72,152 @@ enum Format {
     Cmd,
     Sh,
     Bash,
+    Fish,
+    Xonsh,
+    #[value(alias = "tcsh")]
+    Csh,
+    Cmake,
+    Make,
+    Meson,
+    Cargo,
+    Vscode,
+    #[value(alias = "github")]
+    GithubActions,
+    #[value(alias = "env")]
+    Dotenv,
+    #[value(alias = "raw")]
+    Plain,
     Json,
+    #[value(alias = "yml")]
+    Yaml,
+}
+
+/// Shell named by an executable path (`C:\Program Files\PowerShell\7\pwsh.exe` -> Ps)
+fn shell_from_exe(path: &str) -> Option<Format> {
+    let name = path.rsplit(['\\', '/']).next()?.to_ascii_lowercase();
+    match name.trim_end_matches(".exe") {
+        "pwsh" | "powershell" => Some(Format::Ps),
+        "cmd" => Some(Format::Cmd),
+        "fish" => Some(Format::Fish),
+        "csh" | "tcsh" => Some(Format::Csh),
+        _ => None,
+    }
+}
+
+/// PowerShell (Core or Windows) is running: it adds its per-user / `$PSHOME` module
+/// directories to PSModulePath, which the machine-wide default doesn't contain
+fn in_powershell(vars: &dyn Fn(&str) -> Option<String>) -> bool {
+    vars("PSModulePath").is_some_and(|v| {
+        v.to_ascii_lowercase().split(';').any(|p| {
+            let p = p.trim_end_matches('\\');
+            p.ends_with("\\powershell\\modules") || p.ends_with("\\documents\\windowspowershell\\modules")
+        })
+    })
 }
 
-/// Detect current shell from environment
+/// Detect current shell from the process environment
 fn detect_shell() -> Format {
+    detect_shell_from(&|name| std_env::var(name).ok())
+}
+
+/// Detect shell from environment variables looked up through `vars`
+fn detect_shell_from(vars: &dyn Fn(&str) -> Option<String>) -> Format {
+    // MSYS2 bash sets `_` to the program it launched: a shell started from an
+    // MSYS2 terminal (pwsh, cmd) keeps it, while MSYSTEM is inherited as well
+    if let Some(format) = vars("_").as_deref().and_then(shell_from_exe) {
+        return format;
+    }
+    // fish / xonsh (checked first: both can run under MSYS2, which sets MSYSTEM)
+    if vars("FISH_VERSION").is_some() {
+        return Format::Fish;
+    }
+    if vars("XONSH_VERSION").is_some() {
+        return Format::Xonsh;
+    }
     // MSYS2/Git Bash
-    if std_env::var("MSYSTEM").is_ok() {
+    if vars("MSYSTEM").is_some() {
         return Format::Sh;
     }
-    // CMD always sets PROMPT variable
-    if std_env::var("PROMPT").is_ok() {
+    // CMD always sets PROMPT variable, but PowerShell started from cmd inherits it
+    if vars("PROMPT").is_some() && !in_powershell(vars) {
         return Format::Cmd;
     }
-    // Default to PowerShell on Windows
+    // Default to PowerShell on Windows (same output for pwsh and powershell.exe)
     Format::Ps
 }
 
+/// Append text (plus trailing newline) to a file
+fn append_file(path: &str, text: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    let mut f = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(f, "{}", text)
+}
+
+/// Write GitHub Actions output to `$GITHUB_ENV`/`$GITHUB_PATH`, or stdout outside of Actions
+fn emit_github(env_text: &str, path_text: &str) {
+    match (std_env::var("GITHUB_ENV"), std_env::var("GITHUB_PATH")) {
+        (Ok(env_file), Ok(path_file)) => {
+            for (file, text) in [(&env_file, env_text), (&path_file, path_text)] {
+                if let Err(e) = append_file(file, text) {
+                    eprintln!("Error: Cannot write {}: {}", file, e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        _ => {
+            println!("# GITHUB_ENV");
+            println!("{}", env_text);
+            println!("# GITHUB_PATH");
+            println!("{}", path_text);
+        }
+    }
+}
+
+/// vcvars already ran in this shell: `VSCMD_VER` (e.g. "17.9.2") and `VSCMD_ARG_TGT_ARCH`
+fn vcvars_marker() -> Option<(String, String)> {
+    let ver = std_env::var("VSCMD_VER").ok()?;
+    let arch = std_env::var("VSCMD_ARG_TGT_ARCH").unwrap_or_default();
+    Some((ver, arch))
+}
+
+/// The existing vcvars environment is for the same VS (major.minor) and target arch
+fn vcvars_matches(marker: &(String, String), vs: &detect::VsInfo, target: Arch) -> bool {
+    let major_minor = |v: &str| v.split('.').take(2).collect::<Vec<_>>().join(".");
+    let (ver, arch) = marker;
+    major_minor(ver) == major_minor(&vs.version) && arch.eq_ignore_ascii_case(target.as_str())
+}
+
+/// Version of the `--json-detect` document layout, bumped on incompatible changes
+const DETECT_SCHEMA: u32 = 1;
+
+/// Raw detection results for `--json-detect`
+#[derive(Serialize)]
+struct DetectDoc<'a> {
+    schema: u32,
+    host: Arch,
+    target: Arch,
+    vs: &'a detect::VsInfo,
+    sdk: Option<&'a detect::SdkInfo>,
+    ucrt: Option<&'a detect::SdkInfo>,
+}
+
+/// Print target arch availability table for the given host
+fn print_arch_support(vs: &detect::VsInfo, host: Arch, sdk: Option<&detect::SdkInfo>, ucrt: Option<&detect::SdkInfo>) {
+    let yes_no = |b: bool| if b { "yes" } else { "no" };
+
+    println!("VS {} | VC {} | host {}", vs.version, vs.tools_ver, host.as_str());
+    println!("{:<10}{:<10}{:<10}Usable", "Target", "Compiler", "Libs");
+    for a in detect::arch_support(vs, host, sdk, ucrt) {
+        println!(
+            "{:<10}{:<10}{:<10}{}",
+            a.target,
+            yes_no(a.compiler),
+            yes_no(a.libs),
+            yes_no(a.compiler && a.libs)
+        );
+    }
+}
+
 const EXAMPLES: &str = r#"
 PowerShell:
   vcv | iex                            # Auto-detect, apply to session
@@ -82,21 +227,160 @@ PowerShell:
 
 CMD:
   vcv -f cmd > vcenv.bat && vcenv.bat  # Create and run batch
+  vcv -f cmd -o vcenv.bat              # Same, without shell redirection
   for /f "delims=" %i in ('vcv -f cmd') do @%i
 
+PowerShell module:
+  vcv --emit-psm1 msvc.psm1            # Write module once
+  Import-Module .\msvc.psm1; Enter-VcEnv   # Apply (Exit-VcEnv restores)
+
+Every shell at once:
+  vcv --emit-all dist\vcenv            # env.bat, env.ps1, env.sh, env.fish, env.json
+
 Bash / MSYS2:
-  eval $(vcv -f sh)                    # Apply to current session
+  eval "$(vcv -f sh)"                  # Apply to current session
+  eval "$(vcv -f sh --path-style cygwin)"  # Cygwin: /cygdrive/c/... PATH entries
+
+fish:
+  eval (vcv -f fish)                   # Apply to current session
+
+csh / tcsh:
+  eval `vcv -f csh`                    # Apply to current session
+
+xonsh:
+  execx($(vcv -f xonsh))               # Apply to current session
+
+Nested shells:
+  vcv --force | iex                    # Re-apply even if vcvars already ran (VSCMD_VER)
+
+Path order:
+  vcv --append | iex                   # VS paths after $env:PATH (your tools win)
+  vcv --prefix VCV_ -f sh              # VCV_PATH, VCV_INCLUDE, ... (current ones untouched)
+
+Spectre-mitigated libraries:
+  vcv --spectre | iex                  # Link against lib\spectre\<arch>
+
+Without existing values:
+  vcv --no-inherit | iex               # PATH = VS paths only (no ;$env:PATH)
+  vcv --replace -f cmd > env.bat       # Same (alias), pristine vcvars-like values
+
+Reproducible snapshot:
+  vcv --copy-env-to env.json | iex     # Apply and save the resolved environment
+  vcv --replay env.json | iex          # Re-emit it later without detection
+
+CMake toolchain:
+  vcv -f cmake -q > vcv.cmake          # cmake -DCMAKE_TOOLCHAIN_FILE=vcv.cmake
+
+GNU Make:
+  vcv -f make -q > vcvenv.mk           # include vcvenv.mk
+
+Meson:
+  vcv -f meson -q > vcv.ini            # meson setup --native-file vcv.ini build
+
+Cargo build script:
+  vcv -f cargo -q                      # cargo:rustc-link-search=native=... lines for build.rs
+
+VS Code IntelliSense:
+  vcv -f vscode -q > .vscode\c_cpp_properties.json
+
+GitHub Actions:
+  vcv -f github -q                     # Appends to $GITHUB_ENV / $GITHUB_PATH
+
+dotenv:
+  vcv -f dotenv -q > .env              # For direnv, docker, VS Code envFile
+
+Plain:
+  vcv -f plain -q                      # Bare KEY=VALUE lines, values verbatim
 
 JSON (for tools):
   vcv -f json -q                       # Machine-readable output
+  vcv -f json --with-current -q        # Final PATH etc. including the current entries
+  vcv -f json --json-compact -q | jq   # Single-line JSON for pipes and logs
 
 Cross-compile:
   vcv -a arm64 | iex                   # Build for ARM64
+  vcv -a arm64ec | iex                 # Build ARM64EC (x64-interoperable ARM64)
+  vcv -a arm | iex                     # Build for 32-bit ARM
   vcv -s x64 -a x86 | iex              # Host x64, target x86
 
+vcvarsall-style (host_target, amd64 = x64):
+  vcv amd64 | iex                      # Same as -s x64 -a x64
+  vcv x86_amd64 | iex                  # Same as -s x86 -a x64
+  vcv amd64_arm64 | iex                # Same as -s x64 -a arm64
+
 VS version:
   vcv -v 2019 | iex                    # Use VS 2019 specifically
-  vcv -v 2022 | iex                    # Use VS 2022 specifically"#;
+  vcv -v 2022 | iex                    # Use VS 2022 specifically
+  vcv --prerelease | iex               # Include VS Preview installs
+  vcv --product buildtools | iex       # Only the Build Tools instance
+
+MSVC toolset:
+  vcv --toolset 14.29.30133 | iex      # Use an older side-by-side toolset
+
+Windows SDK:
+  vcv --sdk 10.0.19041.0 | iex         # Pin an exact SDK version
+  vcv --sdk-select oldest | iex        # Build against the oldest installed SDK
+  vcv --ucrt 10.0.19041.0 | iex        # Pin an exact UCRT version
+  vcv --sdk-extra 10.0.19041.0 | iex   # Also an older SDK's headers/libs (after the primary)
+
+Custom vswhere:
+  vcv --vswhere D:\Tools\vswhere.exe | iex
+  $env:VCV_VSWHERE = "D:\Tools\vswhere.exe"
+  vcv --timeout 30 | iex               # Give a slow vswhere more time (default 5s)
+
+Portable VS layout (no vswhere):
+  vcv --vs-path D:\VS\BuildTools | iex
+
+clang-cl:
+  vcv --clang | iex                    # Add VC\Tools\Llvm\<host>\bin, set VCV_CLANG
+
+Bundled CMake/Ninja:
+  vcv --cmake-tools | iex              # cmake/ninja from the VS install on PATH
+
+Lean environment:
+  vcv --no-atlmfc | iex                # Without ATLMFC\include and ATLMFC\lib
+  vcv --debuggers | iex                # windbg/cdb from Windows Kits\10\Debuggers
+  vcv --clr | iex                      # C++/CLI: .NET Framework on PATH/LIBPATH
+  vcv --compiler-only -f dotenv        # Just cl.exe on PATH plus INCLUDE/LIB (for cc-rs etc.)
+  vcv --no-sdk --no-ucrt | iex         # VC++ headers/libs only (freestanding, kernel-mode)
+
+UWP / Store apps:
+  vcv --store | iex                    # lib\<arch>\store + CommonConfiguration\Neutral
+  vcv --winrt | iex                    # WinMDs on LIBPATH, WindowsLibPath for C++/WinRT
+
+Cached detection:
+  vcv --cache | iex                    # Skip vswhere/registry on repeat runs
+  vcv --cache --no-cache | iex         # Force a rescan and refresh the cache
+
+Permanent (registry):
+  vcv --persist user                   # HKCU\Environment, PATH merged with the existing value
+  vcv --persist machine                # System-wide, from an elevated shell
+
+Preview:
+  vcv --diff                           # Only what would be added to PATH/INCLUDE/LIB/LIBPATH
+  vcv --verify                         # Compare with the real vcvarsall.bat (runs it, slow)
+  vcv --get INCLUDE -q                 # Just one variable's value (joined with ;)
+  vcv --get triple -a arm64 -q         # aarch64-pc-windows-msvc (for clang-cl --target)
+  vcv --base-env base.json -f dotenv   # Merge onto a container's environment, not our own
+  vcv --add-include C:\mysdk\inc | iex # Merge extra dirs (also --add-path/-lib/-libpath)
+  vcv --cl-flags /MP | iex             # Default cl.exe options via CL (also --link-flags)
+
+Run a command directly (no shell round-trip):
+  vcv --apply -- cmake --build build   # Exit code is the command's
+  vcv -a x86 --apply -- nmake          # Any vcv options before --
+
+Diagnostics:
+  vcv --self-test                      # Check path assembly on a synthetic layout
+  vcv --install ps --write             # Set up VS in every new PowerShell ($PROFILE)
+  vcv --check || exit 1                # CI gate: silent on success, exit code 2-5 on failure
+  vcv --list                           # Every VS/toolset/SDK/UCRT found, with paths
+  vcv --timing -f json > $null         # Time per phase (vswhere, SDK, UCRT, assembly)
+  vcv --report-missing > $null         # Expected directories that don't exist, by variable
+  vcv --color always 2>&1 | less -R    # Colored versions/warnings even when piped
+  vcv --json-detect                    # Detected VS/SDK/UCRT as JSON (build your own paths)
+  vcv --matrix x64,x86,arm64 -f json   # One JSON line per target arch (NDJSON)
+  vcv --list-arch-support              # Which targets can this machine build?
+  vcv -s arm64 --list-arch-support     # Same, for the ARM64-hosted toolset"#;
 
 #[derive(Parser)]
 #[command(
@@ -105,6 +389,10 @@ VS version:
     after_help = EXAMPLES
 )]
 struct Args {
+    /// vcvarsall-style host_target (x86, amd64, x86_amd64, amd64_x86, amd64_arm64, ...)
+    #[arg(value_name = "VCVARS_ARCH", value_parser = parse_vcvars_arg, conflicts_with_all = ["arch", "host"])]
+    vcvars: Option<(Arch, Arch)>,
+
     /// Target architecture
     #[arg(short = 'a', long = "arch", value_enum, default_value = "x64")]
     arch: Arch,
@@ -117,70 +405,1022 @@ struct Args {
     #[arg(short = 'f', long = "format", value_enum, default_value = "auto")]
     format: Format,
 
+    /// Path to vswhere.exe (default: VCV_VSWHERE, then the VS Installer location)
+    #[arg(long = "vswhere", value_name = "PATH")]
+    vswhere: Option<PathBuf>,
+
+    /// Seconds to wait for vswhere.exe before killing it (0 = no limit)
+    #[arg(long = "timeout", value_name = "SECS", default_value_t = 5)]
+    timeout: u64,
+
+    /// Use the VS installation in this directory (portable layouts, no vswhere)
+    #[arg(long = "vs-path", value_name = "DIR", conflicts_with_all = ["vs_year", "product", "prerelease", "vswhere"])]
+    vs_path: Option<PathBuf>,
+
     /// VS version year (2017, 2019, 2022)
     #[arg(short = 'v', long = "vs")]
     vs_year: Option<u16>,
 
+    /// Only consider this VS product (edition)
+    #[arg(long = "product", value_enum)]
+    product: Option<Product>,
+
+    /// Also consider Visual Studio Preview (prerelease) installs
+    #[arg(long = "prerelease")]
+    prerelease: bool,
+
+    /// MSVC toolset version to use (e.g. 14.29.30133) instead of the default
+    #[arg(long = "toolset", value_name = "VERSION")]
+    toolset: Option<String>,
+
+    /// Windows SDK version to use (e.g. 10.0.19041.0) instead of the newest
+    #[arg(long = "sdk", value_name = "VERSION")]
+    sdk: Option<String>,
+
+    /// Universal CRT version to use (e.g. 10.0.19041.0) instead of the newest
+    #[arg(long = "ucrt", value_name = "VERSION")]
+    ucrt: Option<String>,
+
+    /// Additional Windows SDK version whose headers/libs go after the primary SDK's (repeatable)
+    #[arg(long = "sdk-extra", value_name = "VERSION")]
+    sdk_extra: Vec<String>,
+
+    /// Pick the newest or oldest installed Windows SDK
+    #[arg(long = "sdk-select", value_enum, default_value = "newest")]
+    sdk_select: SdkSelect,
+
+    /// Write output to a file (UTF-8, CRLF for cmd) instead of stdout
+    #[arg(short = 'o', long = "output", value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// PATH entry style in sh/fish/csh output: /c/... (msys), /cygdrive/c/... (cygwin) or C:\... (windows)
+    #[arg(long = "path-style", value_enum, default_value = "msys")]
+    path_style: format::PathStyle,
+
+    /// Convert PATH entries to /c/... style in xonsh output (xonsh under MSYS2/Cygwin)
+    #[arg(long = "unix-paths")]
+    unix_paths: bool,
+
+    /// Write a PowerShell module with Enter-VcEnv/Exit-VcEnv to this file instead of printing
+    #[arg(long = "emit-psm1", value_name = "PATH")]
+    emit_psm1: Option<PathBuf>,
+
+    /// Write env.bat, env.ps1, env.sh, env.fish and env.json into this directory instead of printing
+    #[arg(long = "emit-all", value_name = "DIR", conflicts_with_all = ["emit_psm1", "output"])]
+    emit_all: Option<PathBuf>,
+
+    /// Merge onto the variables in this JSON file (flat `-f json --legacy-json` shape) instead of the current environment
+    #[arg(long = "base-env", value_name = "FILE")]
+    base_env: Option<PathBuf>,
+
+    /// Also save a JSON snapshot of the resolved environment to this file
+    #[arg(long = "copy-env-to", value_name = "FILE")]
+    copy_env_to: Option<PathBuf>,
+
+    /// Emit the environment from a snapshot file, skipping detection
+    #[arg(long = "replay", value_name = "FILE", conflicts_with = "copy_env_to")]
+    replay: Option<PathBuf>,
+
+    /// Add Spectre-mitigated libraries (lib\spectre\<arch>) ahead of the regular ones
+    #[arg(long = "spectre")]
+    spectre: bool,
+
+    /// Add the LLVM bin directory (clang-cl.exe) shipped with VS, and set VCV_CLANG
+    #[arg(long = "clang")]
+    clang: bool,
+
+    /// Add the CMake and Ninja bundled with VS (Common7\IDE\CommonExtensions\Microsoft\CMake) to PATH
+    #[arg(long = "cmake-tools")]
+    cmake_tools: bool,
+
+    /// Link against the UWP/Store libraries (lib\<arch>\store, like vcvars -app_platform=UWP)
+    #[arg(long = "store", visible_alias = "uwp")]
+    store: bool,
+
+    /// C++/WinRT: SDK WinMD metadata and platform.winmd on LIBPATH, set WindowsLibPath/WindowsSDKLibVersion
+    #[arg(long = "winrt", conflicts_with = "compiler_only")]
+    winrt: bool,
+
+    /// Leave the ATL/MFC include and lib directories out of INCLUDE/LIB/LIBPATH
+    #[arg(long = "no-atlmfc")]
+    no_atlmfc: bool,
+
+    /// C++/CLI: add the .NET Framework 4.x dir to PATH/LIBPATH and set FrameworkDir64/FrameworkVersion64
+    #[arg(long = "clr")]
+    clr: bool,
+
+    /// Add windbg/cdb (Windows Kits\10\Debuggers\<host>) to PATH
+    #[arg(long = "debuggers")]
+    debuggers: bool,
+
+    /// Minimal environment for compiling only: VC++ bin on PATH, VC++/SDK um/UCRT INCLUDE and LIB
+    #[arg(long = "compiler-only")]
+    compiler_only: bool,
+
+    /// Leave the Windows SDK out of PATH/INCLUDE/LIB/LIBPATH (freestanding or kernel-mode experiments)
+    #[arg(long = "no-sdk", conflicts_with_all = ["sdk", "sdk_extra"])]
+    no_sdk: bool,
+
+    /// Leave the Universal CRT out of INCLUDE/LIB
+    #[arg(long = "no-ucrt", conflicts_with = "ucrt")]
+    no_ucrt: bool,
+
+    /// Reuse cached detection results from %LOCALAPPDATA%\vcv\cache.json
+    #[arg(long = "cache")]
+    cache: bool,
+
+    /// Ignore cached results and rescan (refreshes the cache with --cache)
+    #[arg(long = "no-cache")]
+    no_cache: bool,
+
+    /// Print the time spent in each phase (vswhere, SDK, UCRT, assembly) to stderr
+    #[arg(long = "timing")]
+    timing: bool,
+
+    /// List every expected directory that was skipped because it doesn't exist (stderr, by variable)
+    #[arg(long = "report-missing")]
+    report_missing: bool,
+
+    /// Color the stderr info and warning lines (auto: only on a terminal, off with -q or NO_COLOR)
+    #[arg(long = "color", value_name = "WHEN", default_value = "auto")]
+    color: color::ColorChoice,
+
     /// Suppress info messages
     #[arg(short = 'q', long = "quiet")]
     quiet: bool,
 
-    /// Skip cl.exe validation
+    /// Emit only the VS paths, without referencing the existing PATH/INCLUDE/LIB/LIBPATH
+    /// (clean environment like a fresh vcvars shell)
+    #[arg(long = "no-inherit", visible_alias = "replace")]
+    no_inherit: bool,
+
+    /// Print the raw VS/SDK/UCRT detection results as versioned JSON (no environment), then exit
+    #[arg(long = "json-detect", conflicts_with = "replay")]
+    json_detect: bool,
+
+    /// Emit one compact JSON document per target arch (NDJSON, each with an "arch" key)
+    #[arg(
+        long = "matrix",
+        value_enum,
+        value_delimiter = ',',
+        value_name = "ARCHES",
+        conflicts_with_all = ["replay", "copy_env_to", "legacy_json", "apply", "diff", "persist", "json_detect"]
+    )]
+    matrix: Option<Vec<Arch>>,
+
+    /// Emit the old flat JSON shape (no schema/metadata) with -f json
+    #[arg(long = "legacy-json")]
+    legacy_json: bool,
+
+    /// With -f json, print the document on a single line instead of pretty-printed
+    #[arg(long = "json-compact")]
+    json_compact: bool,
+
+    /// With -f json, append the current PATH/INCLUDE/LIB/LIBPATH entries (the state after applying)
+    #[arg(long = "with-current")]
+    with_current: bool,
+
+    /// Run COMMAND with the environment applied instead of printing it, exit with its code
+    #[arg(long = "apply", requires = "command")]
+    apply: bool,
+
+    /// Command and arguments for --apply (after `--`)
+    #[arg(last = true, value_name = "COMMAND")]
+    command: Vec<String>,
+
+    /// Write the environment permanently to the user or machine (elevated) registry environment
+    #[arg(long = "persist", value_enum, value_name = "SCOPE")]
+    persist: Option<PersistScope>,
+
+    /// Extra directory for PATH (repeatable)
+    #[arg(long = "add-path", value_name = "DIR")]
+    add_path: Vec<PathBuf>,
+
+    /// Extra directory for INCLUDE (repeatable)
+    #[arg(long = "add-include", value_name = "DIR")]
+    add_include: Vec<PathBuf>,
+
+    /// Extra directory for LIB (repeatable)
+    #[arg(long = "add-lib", value_name = "DIR")]
+    add_lib: Vec<PathBuf>,
+
+    /// Extra directory for LIBPATH (repeatable)
+    #[arg(long = "add-libpath", value_name = "DIR")]
+    add_libpath: Vec<PathBuf>,
+
+    /// Set CL (default cl.exe options, e.g. "/MP") to this value; an existing CL is replaced, not merged
+    #[arg(long = "cl-flags", value_name = "FLAGS", allow_hyphen_values = true)]
+    cl_flags: Option<String>,
+
+    /// Set LINK (default link.exe options, e.g. "/DEBUG") to this value; an existing LINK is replaced, not merged
+    #[arg(long = "link-flags", value_name = "FLAGS", allow_hyphen_values = true)]
+    link_flags: Option<String>,
+
+    /// Print only the value of one variable (PATH, INCLUDE, LIB, LIBPATH or e.g. VCToolsInstallDir),
+    /// or `triple` for the target's LLVM triple (clang-cl --target)
+    #[arg(long = "get", value_name = "VAR", conflicts_with_all = ["diff", "persist", "apply", "matrix"])]
+    get: Option<String>,
+
+    /// Print only the PATH/INCLUDE/LIB/LIBPATH entries missing from the current environment
+    #[arg(long = "diff")]
+    diff: bool,
+
+    /// Run the real vcvarsall.bat and diff its PATH/INCLUDE/LIB/LIBPATH against vcv's (slow)
+    #[arg(long = "verify", conflicts_with_all = ["diff", "get", "persist", "apply", "replay", "base_env"])]
+    verify: bool,
+
+    /// Put the VS paths after the existing PATH/INCLUDE/LIB/LIBPATH instead of before
+    /// (your own tools win over VS ones of the same name)
+    #[arg(long = "append", conflicts_with = "no_inherit")]
+    append: bool,
+
+    /// Prepend STR to every emitted variable name (PATH -> <STR>PATH), for cmd, ps, sh and --legacy-json
+    /// Not with the modes that apply the environment themselves (--persist, --apply, ...)
+    #[arg(
+        long = "prefix",
+        value_name = "STR",
+        conflicts_with_all = ["emit_psm1", "emit_all", "persist", "apply", "get", "diff", "matrix", "verify", "check"]
+    )]
+    prefix: Option<String>,
+
+    /// Emit even when VSCMD_VER shows vcvars already set up the same VS and target
+    #[arg(long = "force")]
+    force: bool,
+
+    /// Skip cl.exe/link.exe/LIB validation
     #[arg(long = "no-validate")]
     no_validate: bool,
+
+    /// Print the profile snippet that runs vcv on shell start (ps, cmd, sh, fish, xonsh, csh)
+    #[arg(long = "install", value_enum, value_name = "SHELL")]
+    install: Option<Format>,
+
+    /// With --install: add the snippet to the shell's profile (cmd: AutoRun) instead of printing it
+    #[arg(long = "write", requires = "install")]
+    write: bool,
+
+    /// Only check for a usable toolchain: exit 0, or 2 (no VS), 3 (no SDK), 4 (no cl.exe), 5 (no target libs)
+    #[arg(long = "check")]
+    check: bool,
+
+    /// Validate path assembly against a synthetic VS/SDK tree, then exit
+    #[arg(long = "self-test")]
+    self_test: bool,
+
+    /// Print every installed VS, MSVC toolset, SDK and UCRT version with paths, then exit
+    #[arg(long = "list")]
+    list: bool,
+
+    /// Show which target arches the detected VS can build for, then exit
+    #[arg(long = "list-arch-support")]
+    list_arch_support: bool,
 }
 
-fn main() {
-    let args = Args::parse();
+/// Spawn `command` with the environment merged onto the inherited one; returns its exit code
+fn run_with_env(env: &env::Env, inherit: bool, command: &[String]) -> i32 {
+    let (program, rest) = command.split_first().expect("--apply requires a command");
+    let status = std::process::Command::new(program)
+        .args(rest)
+        .envs(env.merged_vars(inherit))
+        .status();
+    match status {
+        Ok(status) => status.code().unwrap_or(1),
+        Err(e) => {
+            eprintln!("Error: Cannot run {}: {}", program, e);
+            1
+        }
+    }
+}
 
-    // Validate VS year if specified
-    if let Some(year) = args.vs_year {
-        if !matches!(year, 2017 | 2019 | 2022) {
-            eprintln!("Error: Invalid VS year {}. Use 2017, 2019, or 2022", year);
-            std::process::exit(1);
+/// Cache key: every argument that affects detection
+fn cache_key(args: &Args) -> String {
+    format!(
+        "vs={:?};path={:?};vswhere={:?};pre={};product={:?};host={};target={};\
+         toolset={:?};sdk={:?};select={:?};ucrt={:?}",
+        args.vs_year,
+        args.vs_path,
+        vswhere_override(args),
+        args.prerelease,
+        args.product,
+        args.host.as_str(),
+        args.arch.as_str(),
+        args.toolset,
+        args.sdk,
+        args.sdk_select,
+        args.ucrt
+    )
+}
+
+/// vswhere.exe override: --vswhere, then VCV_VSWHERE
+fn vswhere_override(args: &Args) -> Option<PathBuf> {
+    args.vswhere.clone().or_else(|| std_env::var_os("VCV_VSWHERE").map(PathBuf::from))
+}
+
+/// Resolve vswhere.exe: --vswhere, then VCV_VSWHERE, then the default location
+/// Exits when an explicit override doesn't exist
+fn resolve_vswhere(args: &Args) -> PathBuf {
+    match vswhere_override(args) {
+        Some(p) => {
+            if !p.exists() {
+                eprintln!("Error: vswhere.exe not found at {}", p.display());
+                std::process::exit(1);
+            }
+            p
         }
+        None => PathBuf::from(detect::DEFAULT_VSWHERE),
     }
+}
+
+/// Print every detected VS install, toolset, SDK and UCRT version with full paths (`--list`)
+fn print_list(args: &Args) {
+    let vswhere = resolve_vswhere(args);
+
+    println!("Visual Studio ({}):", vswhere.display());
+    let installs = detect::list_vs_installs(&vswhere, args.prerelease, args.product);
+    if installs.is_empty() {
+        println!("  (none)");
+    }
+    for (version, product, install) in installs {
+        println!("  {} {}  {}", version, product, install.display());
+        let default = detect::vs_from_path(&install, None).map(|vs| vs.tools_ver);
+        let msvc = install.join("VC").join("Tools").join("MSVC");
+        for t in detect::toolsets_in(&install) {
+            let mark = if default.as_deref() == Some(t.as_str()) { " (default)" } else { "" };
+            println!("    MSVC {}{}  {}", t, mark, msvc.join(&t).display());
+        }
+    }
+
+    println!("Windows SDK:");
+    let sdks = detect::list_sdks();
+    match detect::sdk_root() {
+        Some(root) if !sdks.is_empty() => {
+            for v in sdks {
+                println!("  {}  {}", v, root.join("include").join(&v).display());
+            }
+        }
+        _ => println!("  (none)"),
+    }
+
+    println!("Universal CRT:");
+    let ucrt_root = detect::ucrt_root();
+    let ucrts = ucrt_root.as_deref().map(detect::ucrt_versions).unwrap_or_default();
+    match ucrt_root {
+        Some(root) if !ucrts.is_empty() => {
+            for v in ucrts {
+                println!("  {}  {}", v, root.join("Lib").join(&v).join("ucrt").display());
+            }
+        }
+        _ => println!("  (none)"),
+    }
+}
+
+/// Run one phase, printing its wall-clock time to stderr with `--timing` (unless `-q`)
+fn timed<T>(args: &Args, phase: &str, f: impl FnOnce() -> T) -> T {
+    let start = std::time::Instant::now();
+    let out = f();
+    if args.timing && !args.quiet {
+        eprintln!("# {:<14} {:>8.2} ms", phase, start.elapsed().as_secs_f64() * 1000.0);
+    }
+    out
+}
+
+/// VS from --vs-path, else via vswhere
+fn find_vs(args: &Args, vswhere: &Path) -> Option<detect::VsInfo> {
+    match &args.vs_path {
+        Some(dir) => detect::vs_from_path(dir, args.toolset.as_deref()),
+        None => detect::detect_vs(vswhere, args.prerelease, args.product, args.vs_year, args.toolset.as_deref()),
+    }
+}
+
+/// Exit with an error when `-v` names a year other than 2017, 2019 or 2022
+fn validate_vs_year(args: &Args) {
+    if let Some(year) = args.vs_year
+        && !matches!(year, 2017 | 2019 | 2022)
+    {
+        eprintln!("Error: Invalid VS year {}. Use 2017, 2019, or 2022", year);
+        std::process::exit(1);
+    }
+}
 
-    // Detect VS
-    let vs = match detect::detect_vs(args.vs_year) {
+/// Run vswhere/registry detection for VS, SDK and UCRT
+/// Exits with an error (listing alternatives) when a requested component is missing
+fn detect_fresh(args: &Args) -> CacheEntry {
+    validate_vs_year(args);
+    let vswhere = resolve_vswhere(args);
+
+    // VS (vswhere subprocess), SDK and UCRT (registry + directory scans) are independent:
+    // run them on scoped threads that only borrow `args`/`vswhere` immutably
+    let (found, sdk, ucrt) = timed(args, "detection", || {
+        std::thread::scope(|s| {
+            let vs = s.spawn(|| {
+                timed(args, "vswhere", || find_vs(args, &vswhere))
+            });
+            let sdk = s.spawn(|| timed(args, "SDK lookup", || detect::detect_sdk(args.sdk_select, args.sdk.as_deref())));
+            let ucrt = s.spawn(|| timed(args, "UCRT lookup", || detect::detect_ucrt(args.ucrt.as_deref())));
+            (
+                vs.join().expect("VS detection thread panicked"),
+                sdk.join().expect("SDK detection thread panicked"),
+                ucrt.join().expect("UCRT detection thread panicked"),
+            )
+        })
+    });
+
+    let vs = match found {
         Some(vs) => vs,
         None => {
-            if let Some(year) = args.vs_year {
-                eprintln!("Error: Visual Studio {} not found", year);
-                let versions = detect::list_vs_versions();
+            if let Some(dir) = &args.vs_path {
+                let toolset = args.toolset.as_deref().unwrap_or("toolset");
+                eprintln!(
+                    "Error: no usable MSVC {} in {} (expected VC\\Tools\\MSVC\\<version>)",
+                    toolset,
+                    dir.display()
+                );
+                let toolsets = detect::toolsets_in(dir);
+                if !toolsets.is_empty() {
+                    eprintln!("Available toolsets:");
+                    for t in toolsets {
+                        eprintln!("  {}", t);
+                    }
+                }
+            } else if let Some(ref toolset) = args.toolset
+                && let Some(vs) = detect::detect_vs(&vswhere, args.prerelease, args.product, args.vs_year, None)
+            {
+                eprintln!("Error: MSVC toolset {} not found in {}", toolset, vs.install.display());
+                let toolsets = detect::list_toolsets(&vs);
+                if !toolsets.is_empty() {
+                    eprintln!("Available toolsets:");
+                    for t in toolsets {
+                        eprintln!("  {}", t);
+                    }
+                }
+            } else if args.vs_year.is_some() || args.product.is_some() {
+                let year = args.vs_year.map(|y| format!(" {}", y)).unwrap_or_default();
+                let product = args.product.map(|p| format!(" {}", p.name())).unwrap_or_default();
+                eprintln!("Error: Visual Studio{}{} not found", year, product);
+                let versions = detect::list_vs_versions(&vswhere, args.prerelease, None);
                 if !versions.is_empty() {
                     eprintln!("Available versions:");
-                    for (y, v) in versions {
-                        eprintln!("  {} ({})", y, v);
+                    for (y, v, p) in versions {
+                        eprintln!("  {} ({}, {})", y, v, p);
                     }
                 }
             } else {
                 eprintln!("Error: Visual Studio not found");
+                if !args.quiet
+                    && let Some(e) = detect::vswhere_error(&vswhere, args.prerelease, args.product)
+                {
+                    eprintln!("vswhere: {}", e);
+                }
             }
             std::process::exit(1);
         }
     };
 
-    let sdk = detect::detect_sdk();
-    let ucrt = detect::detect_ucrt();
+    if let Some(ref version) = args.sdk
+        && sdk.is_none()
+    {
+        eprintln!("Error: Windows SDK {} not found", version);
+        let sdks = detect::list_sdks();
+        if !sdks.is_empty() {
+            eprintln!("Available SDKs:");
+            for s in sdks {
+                eprintln!("  {}", s);
+            }
+        }
+        std::process::exit(1);
+    }
+
+    if let Some(ref version) = args.ucrt
+        && ucrt.is_none()
+    {
+        eprintln!("Error: Universal CRT {} not found", version);
+        let ucrts = detect::list_ucrts();
+        if !ucrts.is_empty() {
+            eprintln!("Available UCRT versions:");
+            for u in ucrts {
+                eprintln!("  {}", u);
+            }
+        }
+        std::process::exit(1);
+    }
+
+    let compiler_host = detect::compiler_host(&vs, args.host, args.arch);
+    CacheEntry { vs, sdk, ucrt, compiler_host }
+}
+
+/// Detect VS/SDK/UCRT (cached or fresh) and print the info lines
+/// Exits on fatal detection errors (and after `--list-arch-support`/`--json-detect`)
+fn detect_cached(args: &Args) -> CacheEntry {
+    // Reuse validated cached results unless --no-cache
+    let key = cache_key(args);
+    let cached = if args.cache && !args.no_cache {
+        cache::load(&key, args.host, args.arch)
+    } else {
+        None
+    };
+    let CacheEntry { vs, sdk, ucrt, compiler_host } = match cached {
+        Some(entry) => entry,
+        None => {
+            let entry = detect_fresh(args);
+            if args.cache {
+                cache::store(&key, &entry);
+            }
+            entry
+        }
+    };
+
+    if args.list_arch_support {
+        print_arch_support(&vs, args.host, sdk.as_ref(), ucrt.as_ref());
+        std::process::exit(0);
+    }
+
+    if args.json_detect {
+        let doc = DetectDoc {
+            schema: DETECT_SCHEMA,
+            host: args.host,
+            target: args.arch,
+            vs: &vs,
+            sdk: sdk.as_ref(),
+            ucrt: ucrt.as_ref(),
+        };
+        println!("{}", serde_json::to_string_pretty(&doc).unwrap());
+        std::process::exit(0);
+    }
+
+    // --no-sdk / --no-ucrt: detected, but left out of the environment
+    let sdk = sdk.filter(|_| !args.no_sdk);
+    let ucrt = ucrt.filter(|_| !args.no_ucrt);
 
     // Print info to stderr
     if !args.quiet {
-        eprintln!("# VS {} | VC {}", vs.version, vs.tools_ver);
+        eprintln!("# VS {} | VC {}", color::version(&vs.version), color::version(&vs.tools_ver));
         if let Some(ref s) = sdk {
-            eprintln!("# SDK {}", s.version);
+            if s.version.is_empty() {
+                eprintln!("# SDK (versionless layout) {}", s.path.display());
+            } else {
+                eprintln!("# SDK {}", color::sdk(&s.version));
+            }
+            if !s.has_headers() {
+                eprintln!(
+                    "{} Windows SDK {} headers appear to be missing (libraries only)",
+                    color::warning(),
+                    s.version
+                );
+            }
+        }
+        if let (Some(s), Some(u)) = (&sdk, &ucrt)
+            && !s.version.is_empty()
+            && s.version != u.version
+        {
+            eprintln!(
+                "{} Windows SDK {} and UCRT {} versions differ (may cause link errors; pin with --sdk/--ucrt)",
+                color::warning(),
+                s.version,
+                u.version
+            );
+        }
+    }
+
+    CacheEntry { vs, sdk, ucrt, compiler_host }
+}
+
+/// Environment options from the command line
+fn env_opts(args: &Args) -> env::EnvOpts {
+    env::EnvOpts {
+        spectre: args.spectre,
+        clang: args.clang,
+        cmake_tools: args.cmake_tools,
+        store: args.store,
+        no_atlmfc: args.no_atlmfc,
+        clr: args.clr,
+        debuggers: args.debuggers,
+        // The registry's Kits root, so --no-ucrt doesn't drop the debuggers
+        kits_root: if args.debuggers { detect::ucrt_root() } else { None },
+        winrt: args.winrt,
+        compiler_only: args.compiler_only,
+    }
+}
+
+/// `--check` exit codes (stable, documented in the README)
+const EXIT_NO_VS: i32 = 2;
+const EXIT_NO_SDK: i32 = 3;
+const EXIT_NO_CL: i32 = 4;
+const EXIT_NO_LIB: i32 = 5;
+
+/// `--check`: probe for a usable toolchain without printing anything on success
+/// Returns 0 or the exit code of the first missing piece (VS, SDK, cl.exe, target libs);
+/// an invalid `-v` year exits with 1 before anything is probed
+fn run_check(args: &Args) -> i32 {
+    validate_vs_year(args);
+    let fail = |code: i32, msg: String| {
+        if !args.quiet {
+            eprintln!("Error: {}", msg);
+        }
+        code
+    };
+
+    let vswhere = resolve_vswhere(args);
+    let Some(vs) = find_vs(args, &vswhere) else {
+        return fail(EXIT_NO_VS, "Visual Studio not found".into());
+    };
+    // --no-sdk / --no-ucrt: not needed, so not probed
+    let sdk = if args.no_sdk {
+        None
+    } else {
+        match detect::detect_sdk(args.sdk_select, args.sdk.as_deref()) {
+            Some(sdk) => Some(sdk),
+            None => return fail(EXIT_NO_SDK, "Windows SDK not found".into()),
+        }
+    };
+    let ucrt = if args.no_ucrt { None } else { detect::detect_ucrt(args.ucrt.as_deref()) };
+
+    let env = env::build_env(&vs, sdk.as_ref(), ucrt.as_ref(), args.host, args.arch, &env_opts(args));
+    if env.cl_path().is_none() {
+        return fail(EXIT_NO_CL, format!("cl.exe not found for {} -> {}", args.host.as_str(), args.arch.as_str()));
+    }
+    if !env.lib.iter().any(|p| p.exists()) {
+        return fail(EXIT_NO_LIB, format!("no LIB directory exists for target {}", args.arch.as_str()));
+    }
+    0
+}
+
+/// `--report-missing`: candidate directories `build_env` dropped, grouped by variable
+fn report_missing(env: &env::Env) {
+    let mut seen = std::collections::HashSet::new();
+    let missing: Vec<_> = env.missing.iter().filter(|entry| seen.insert(*entry)).collect();
+    if missing.is_empty() {
+        eprintln!("# No expected directories are missing");
+        return;
+    }
+    for var in ["PATH", "INCLUDE", "LIB", "LIBPATH"] {
+        let paths: Vec<_> = missing.iter().filter(|(v, _)| *v == var).collect();
+        if !paths.is_empty() {
+            eprintln!("# Missing {} candidates:", var);
+            for (_, p) in paths {
+                eprintln!("#   {}", p.display());
+            }
+        }
+    }
+}
+
+/// Assemble the environment for one target from detection results
+fn assemble_env(
+    args: &Args,
+    vs: &detect::VsInfo,
+    sdk: Option<&detect::SdkInfo>,
+    ucrt: Option<&detect::SdkInfo>,
+    target: Arch,
+) -> env::Env {
+    let env_opts = env_opts(args);
+    let mut env = timed(args, "env assembly", || env::build_env(vs, sdk, ucrt, args.host, target, &env_opts));
+    if let Some(sdk) = sdk {
+        add_extra_sdks(args, &mut env, sdk, target);
+    }
+    add_user_paths(args, &mut env);
+    if !args.quiet
+        && let Some(used) = detect::compiler_host(vs, args.host, target)
+        && used != args.host
+    {
+        eprintln!(
+            "{} no {}-hosted {} compiler installed, using bin\\{}\\{} instead",
+            color::warning(),
+            args.host.as_str(),
+            target.as_str(),
+            used.host_dir(),
+            target.bin_dir()
+        );
+    }
+    for (name, flags) in [("CL", &args.cl_flags), ("LINK", &args.link_flags)] {
+        if let Some(flags) = flags {
+            env.vars.insert(name.into(), flags.clone());
+        }
+    }
+    if args.report_missing {
+        report_missing(&env);
+    }
+    if args.clang && !args.quiet && !env.vars.contains_key("VCV_CLANG") {
+        let llvm = vs.vc.join("Tools").join("Llvm");
+        eprintln!(
+            "{} clang-cl.exe not found in {} (install the C++ Clang tools component)",
+            color::warning(),
+            llvm.display()
+        );
+    }
+    if args.debuggers && !args.quiet {
+        let dbg = env::debuggers_dir(ucrt, args.host, &env_opts);
+        if !dbg.as_ref().is_some_and(|d| env.path.contains(d)) {
+            let at = dbg.map(|d| format!(" in {}", d.display())).unwrap_or_default();
+            eprintln!(
+                "{} Debugging Tools for Windows not found{} (install them with the Windows SDK)",
+                color::warning(),
+                at
+            );
+        }
+    }
+    if args.clr && !args.quiet && !env.vars.contains_key("FrameworkDir64") {
+        eprintln!("{} .NET Framework 4.x not found (Microsoft.NET\\Framework64\\v4.*)", color::warning());
+    }
+    env
+}
+
+/// `--base-env`: read a flat JSON object of variables (what `--legacy-json` prints);
+/// the `env` part of a schema document (`-f json`) is flattened to the same shape
+fn load_base_env(path: &Path) -> Result<BTreeMap<String, serde_json::Value>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut base: BTreeMap<String, serde_json::Value> =
+        serde_json::from_str(&text).map_err(|e| format!("invalid base environment: {}", e))?;
+    if base.contains_key("schema")
+        && let Some(serde_json::Value::Object(mut env)) = base.remove("env")
+    {
+        let vars = env.remove("vars");
+        base = env.into_iter().collect();
+        if let Some(serde_json::Value::Object(vars)) = vars {
+            base.extend(vars);
+        }
+    }
+    Ok(base)
+}
+
+/// `--sdk-extra`: headers and libs of more SDK versions after the primary SDK's
+/// Versions without headers under the primary SDK's root are skipped with a warning
+fn add_extra_sdks(args: &Args, env: &mut env::Env, sdk: &detect::SdkInfo, target: Arch) {
+    let tlib = target.lib_dir();
+    for version in &args.sdk_extra {
+        if detect::sdk_from_root(&sdk.path, args.sdk_select, Some(version)).is_none() {
+            eprintln!("{} Windows SDK {} not found in {}, not added", color::warning(), version, sdk.path.display());
+            continue;
+        }
+        let include = sdk.path.join("include").join(version);
+        let lib = sdk.path.join("Lib").join(version);
+        let dirs = ["um", "shared", "ucrt"].map(|d| include.join(d));
+        env.include.extend(dirs.into_iter().filter(|d| d.exists()));
+        let libs = ["um", "ucrt"].map(|d| lib.join(d).join(&tlib));
+        env.lib.extend(libs.into_iter().filter(|d| d.exists()));
+    }
+}
+
+/// `--add-path`/`--add-include`/`--add-lib`/`--add-libpath`: after the detected entries
+/// (before them with `--append`); missing directories are skipped with a warning
+fn add_user_paths(args: &Args, env: &mut env::Env) {
+    let extra = [
+        (&args.add_path, &mut env.path),
+        (&args.add_include, &mut env.include),
+        (&args.add_lib, &mut env.lib),
+        (&args.add_libpath, &mut env.libpath),
+    ];
+    for (dirs, list) in extra {
+        let found: Vec<PathBuf> = dirs
+            .iter()
+            .filter(|d| {
+                let ok = d.exists();
+                if !ok {
+                    eprintln!("{} {} does not exist, not added", color::warning(), d.display());
+                }
+                ok
+            })
+            .cloned()
+            .collect();
+        if args.append {
+            list.splice(0..0, found);
+        } else {
+            list.extend(found);
+        }
+    }
+    env.dedup();
+}
+
+/// Detect VS/SDK/UCRT and assemble the environment for `--arch`
+fn detect_all(args: &Args) -> Snapshot {
+    let CacheEntry { vs, sdk, ucrt, .. } = detect_cached(args);
+    let env = assemble_env(args, &vs, sdk.as_ref(), ucrt.as_ref(), args.arch);
+    Snapshot { host: args.host, target: args.arch, vs, sdk, ucrt, env }
+}
+
+/// Machine-readable codes for silent partial failures of detection and assembly
+/// (the JSON `warnings` array). Components left out with --no-sdk/--no-ucrt aren't reported
+fn diagnostics(
+    args: &Args,
+    env: &env::Env,
+    vs: &detect::VsInfo,
+    sdk: Option<&detect::SdkInfo>,
+    ucrt: Option<&detect::SdkInfo>,
+    target: Arch,
+) -> Vec<&'static str> {
+    let mut codes = Vec::new();
+    if sdk.is_none() && !args.no_sdk {
+        codes.push("sdk_not_found");
+    }
+    if ucrt.is_none() && !args.no_ucrt {
+        codes.push("ucrt_not_found");
+    }
+    for (what, _) in detect::missing_target_libs(sdk, ucrt, target) {
+        codes.push(if what == "UCRT" { "ucrt_lib_missing" } else { "sdk_lib_missing" });
+    }
+    match env.cl_path() {
+        None => codes.push("cl_not_on_path"),
+        Some(cl) if !cl.with_file_name("link.exe").exists() => codes.push("link_not_found"),
+        Some(_) => {}
+    }
+    if !env.lib.iter().any(|p| p.exists()) {
+        codes.push("lib_dirs_missing");
+    }
+    if args.host != target && !vs.tools.join("lib").join(target.lib_dir()).exists() {
+        codes.push("target_vc_lib_missing");
+    }
+    codes
+}
+
+/// `--matrix`: detect once, then print one compact JSON document per target (NDJSON)
+fn print_matrix(args: &Args, targets: &[Arch]) {
+    if !matches!(args.format, Format::Auto | Format::Json) {
+        eprintln!("Error: --matrix only supports -f json");
+        std::process::exit(1);
+    }
+    let CacheEntry { vs, sdk, ucrt, .. } = detect_cached(args);
+    for &target in targets {
+        let env = assemble_env(args, &vs, sdk.as_ref(), ucrt.as_ref(), target);
+        if let Err(e) = env.check_keys("") {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
         }
+        let warnings = diagnostics(args, &env, &vs, sdk.as_ref(), ucrt.as_ref(), target);
+        println!("{}", format::fmt_json_line(&env, target, &vs, sdk.as_ref(), ucrt.as_ref(), &warnings));
+    }
+}
+
+fn main() {
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    // Config files fill in whatever the command line left at its default
+    let configs = config::load_all().unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+    for (path, cfg) in &configs {
+        if let Err(e) = cfg.apply(&mut args, &matches) {
+            eprintln!("Error: {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+
+    color::init(args.color, args.quiet);
+    check_prefix(&args);
+    detect::set_vswhere_timeout(std::time::Duration::from_secs(args.timeout));
+
+    if args.self_test {
+        std::process::exit(if selftest::run() { 0 } else { 1 });
+    }
+
+    if args.list {
+        print_list(&args);
+        return;
+    }
+
+    if let Some(shell) = args.install {
+        install::run(shell, args.write, args.quiet);
+        return;
+    }
+
+    // vcvarsall-style positional arg replaces -s/-a
+    if let Some((host, target)) = args.vcvars {
+        args.host = host;
+        args.arch = target;
+    }
+
+    if matches!(args.host, Arch::Arm | Arch::Arm64ec) {
+        eprintln!("Error: {} is a target-only architecture. Use -s x64, x86 or arm64", args.host.as_str());
+        std::process::exit(1);
+    }
+
+    if args.check {
+        std::process::exit(run_check(&args));
+    }
+
+    if let Some(targets) = &args.matrix {
+        print_matrix(&args, targets);
+        return;
+    }
+
+    let mut snap = match &args.replay {
+        Some(path) => match Snapshot::load(path) {
+            Ok(snap) => snap,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        },
+        None => detect_all(&args),
+    };
+
+    if let Some(path) = &args.copy_env_to
+        && let Err(e) = snap.save(path)
+    {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
     }
 
-    // Build environment
-    let env = env::build_env(&vs, sdk.as_ref(), ucrt.as_ref(), args.host, args.arch);
+    let mut env = std::mem::take(&mut snap.env);
+
+    if let Some(path) = &args.base_env
+        && let Err(e) = load_base_env(path).and_then(|base| env.merge_base(&base))
+    {
+        eprintln!("Error: {}: {}", path.display(), e);
+        std::process::exit(1);
+    }
+    if let Err(e) = env.check_keys(args.prefix.as_deref().unwrap_or("")) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+    // The merged values are complete: don't reference the process's own PATH as well
+    let inherit = !args.no_inherit && args.base_env.is_none();
 
-    // Validate cl.exe exists
+    // Validate compiler, linker and target libraries separately
     if !args.no_validate {
-        let cl_exists = env.path.iter().any(|p| p.join("cl.exe").exists());
-        if !cl_exists {
-            eprintln!("Warning: cl.exe not found in PATH");
+        match env.cl_path() {
+            None => eprintln!("{} cl.exe not found in PATH (compiler missing)", color::warning()),
+            Some(cl) => {
+                if !cl.with_file_name("link.exe").exists() {
+                    eprintln!("{} link.exe not found next to {} (linker missing)", color::warning(), cl.display());
+                }
+            }
+        }
+        if !env.lib.iter().any(|p| p.exists()) {
+            eprintln!(
+                "{} no LIB directory exists for target {} (libraries missing)",
+                color::warning(),
+                snap.target.as_str()
+            );
+        }
+        // Cross builds: the host's compiler can be installed without the target's VC++ libs
+        // (e.g. an arm64-first install used with -a x86), which only fails at link time
+        let target_libs = snap.vs.tools.join("lib").join(snap.target.lib_dir());
+        if snap.host != snap.target && !target_libs.exists() {
+            eprintln!(
+                "{} target {} libraries not installed ({} missing) - add the component in the VS Installer",
+                color::warning(),
+                snap.target.as_str(),
+                target_libs.display()
+            );
+        }
+        for (what, dir) in detect::missing_target_libs(snap.sdk.as_ref(), snap.ucrt.as_ref(), snap.target) {
+            let arch = snap.target.as_str().to_uppercase();
+            eprintln!(
+                "{} {} {} libraries not installed ({} missing) - add the {} component of the Windows SDK",
+                color::warning(),
+                what,
+                arch,
+                dir.display(),
+                arch
+            );
+        }
+    }
+
+    if let Some(name) = &args.get {
+        if name.eq_ignore_ascii_case("triple") {
+            println!("{}", snap.target.llvm_triple());
+            return;
+        }
+        match env.get(name) {
+            Some(value) => println!("{}", value),
+            None => {
+                eprintln!("Error: {} is not set by vcv", name);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if args.verify {
+        std::process::exit(verify::run(&snap.vs, snap.host, snap.target, &env, args.quiet));
+    }
+
+    if args.diff {
+        for (name, entries) in env.new_entries() {
+            println!("{}:", name);
+            if entries.is_empty() {
+                println!("  (nothing new)");
+            }
+            for p in entries {
+                println!("  + {}", p.display());
+            }
         }
+        return;
+    }
+
+    if let Some(scope) = args.persist {
+        if let Err(e) = env::persist(&env, scope) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        if !args.quiet {
+            eprintln!("# Persisted to the {:?} environment (new shells will see it)", scope);
+        }
+        return;
+    }
+
+    if args.apply {
+        std::process::exit(run_with_env(&env, inherit, &args.command));
     }
 
     // Resolve format
@@ -189,13 +1429,221 @@ fn main() {
         other => other,
     };
 
-    let output = match format {
-        Format::Cmd => format::fmt_cmd(&env),
-        Format::Ps | Format::Powershell => format::fmt_ps(&env),
-        Format::Sh | Format::Bash => format::fmt_sh(&env),
-        Format::Json => format::fmt_json(&env),
-        Format::Auto => unreachable!(),
+    // Don't stack a second copy of the paths onto a shell that already ran vcvars
+    // (only for shell code printed to be evaluated; files and structured output are always written)
+    let shell_code = matches!(
+        format,
+        Format::Cmd | Format::Ps | Format::Powershell | Format::Sh | Format::Bash
+            | Format::Fish | Format::Csh | Format::Xonsh
+    );
+    let to_stdout = args.output.is_none() && args.emit_psm1.is_none() && args.emit_all.is_none();
+    if shell_code
+        && to_stdout
+        && !args.force
+        && let Some(marker) = vcvars_marker()
+        && vcvars_matches(&marker, &snap.vs, snap.target)
+    {
+        if !args.quiet {
+            eprintln!(
+                "# vcvars already set up VS {} ({}) in this shell, nothing emitted (use --force to re-apply)",
+                marker.0, marker.1
+            );
+        }
+        return;
+    }
+
+    if args.with_current {
+        if !matches!(format, Format::Json) {
+            eprintln!("Error: --with-current only applies to -f json");
+            std::process::exit(1);
+        }
+        if inherit {
+            env.append_current();
+        }
+    }
+
+    if matches!(format, Format::GithubActions) && args.output.is_some() {
+        eprintln!("Error: -f github writes to $GITHUB_ENV/$GITHUB_PATH and can't be combined with -o");
+        std::process::exit(1);
+    }
+
+    let fmt_opts = format::FmtOpts {
+        inherit,
+        unix_paths: args.unix_paths,
+        path_style: args.path_style,
+        append: args.append,
+        prefix: args.prefix.as_deref().unwrap_or(""),
+    };
+
+    if let Some(path) = &args.emit_psm1 {
+        if let Err(e) = std::fs::write(path, format::fmt_psm1(&env, &fmt_opts)) {
+            eprintln!("Error: Cannot write {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+        if !args.quiet {
+            eprintln!("# Wrote {}", path.display());
+        }
+        return;
+    }
+
+    if let Some(dir) = &args.emit_all {
+        emit_all(dir, &env, &snap, &fmt_opts, &args);
+        return;
+    }
+
+    if let Format::GithubActions = format {
+        let (env_text, path_text) = format::fmt_github(&env, &fmt_opts);
+        emit_github(&env_text, &path_text);
+        return;
+    }
+
+    let output = render(format, &env, &snap, &fmt_opts, &args);
+
+    match &args.output {
+        Some(path) => {
+            if let Err(e) = std::fs::write(path, file_text(format, &output)) {
+                eprintln!("Error: Cannot write {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
+        None => println!("{}", output),
+    }
+}
+
+/// `--prefix` only renames variables in formats that name them: exit with an error otherwise
+fn check_prefix(args: &Args) {
+    if args.prefix.is_none() {
+        return;
+    }
+    let format = match args.format {
+        Format::Auto => detect_shell(),
+        other => other,
+    };
+    let supported = match format {
+        Format::Cmd | Format::Ps | Format::Powershell | Format::Sh | Format::Bash => true,
+        Format::Json => args.legacy_json,
+        _ => false,
     };
+    if !supported {
+        eprintln!("Error: --prefix supports cmd, ps, sh and json with --legacy-json (the schema has fixed names)");
+        std::process::exit(1);
+    }
+}
+
+/// Run the formatter for `format` (GitHub Actions and auto are resolved by the caller)
+fn render(format: Format, env: &env::Env, snap: &Snapshot, opts: &format::FmtOpts, args: &Args) -> String {
+    match format {
+        Format::Cmd => format::fmt_cmd(env, opts),
+        Format::Ps | Format::Powershell => format::fmt_ps(env, opts),
+        Format::Sh | Format::Bash => format::fmt_sh(env, opts),
+        Format::Fish => format::fmt_fish(env, opts),
+        Format::Xonsh => format::fmt_xonsh(env, opts),
+        Format::Csh => format::fmt_csh(env, opts),
+        Format::Cmake => format::fmt_cmake(env, snap.host, snap.target, opts),
+        Format::Make => format::fmt_make(env, opts),
+        Format::Meson => format::fmt_meson(env),
+        Format::Cargo => format::fmt_cargo(env),
+        Format::Vscode => format::fmt_vscode(env, snap.target),
+        Format::Dotenv => format::fmt_dotenv(env),
+        Format::Plain => format::fmt_plain(env),
+        Format::Json if args.legacy_json => format::fmt_json(env, opts.prefix, args.json_compact),
+        Format::Json => {
+            let (sdk, ucrt) = (snap.sdk.as_ref(), snap.ucrt.as_ref());
+            let warnings = diagnostics(args, env, &snap.vs, sdk, ucrt, snap.target);
+            format::fmt_json_schema(env, snap.target, &snap.vs, sdk, ucrt, &warnings, args.json_compact)
+        }
+        Format::Yaml => format::fmt_yaml(env),
+        Format::GithubActions | Format::Auto => unreachable!(),
+    }
+}
+
+/// File contents for formatter output: UTF-8 without BOM, CRLF line endings for cmd.exe
+fn file_text(format: Format, output: &str) -> String {
+    match format {
+        Format::Cmd => format!("{}\r\n", output.replace('\n', "\r\n")),
+        _ => format!("{}\n", output),
+    }
+}
 
-    println!("{}", output);
+/// Files written by `--emit-all`, one per shell
+const EMIT_ALL: [(&str, Format); 5] = [
+    ("env.bat", Format::Cmd),
+    ("env.ps1", Format::Ps),
+    ("env.sh", Format::Sh),
+    ("env.fish", Format::Fish),
+    ("env.json", Format::Json),
+];
+
+/// Write every `EMIT_ALL` file into `dir` (created if missing) from the same environment
+fn emit_all(dir: &Path, env: &env::Env, snap: &Snapshot, opts: &format::FmtOpts, args: &Args) {
+    if let Err(e) = std::fs::create_dir_all(dir) {
+        eprintln!("Error: Cannot create {}: {}", dir.display(), e);
+        std::process::exit(1);
+    }
+    for (name, format) in EMIT_ALL {
+        let path = dir.join(name);
+        let output = render(format, env, snap, opts, args);
+        if let Err(e) = std::fs::write(&path, file_text(format, &output)) {
+            eprintln!("Error: Cannot write {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+    if !args.quiet {
+        let names: Vec<_> = EMIT_ALL.iter().map(|(name, _)| *name).collect();
+        eprintln!("# Wrote {} to {}", names.join(", "), dir.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Shell detected from exactly the given variables
+    fn shell_for(vars: &[(&str, &str)]) -> Format {
+        let map: HashMap<&str, &str> = vars.iter().copied().collect();
+        detect_shell_from(&|name| map.get(name).map(|v| v.to_string()))
+    }
+
+    #[test]
+    fn base_env_case_collision() {
+        let file = std::env::temp_dir().join(format!("vcv-base-{}.json", std::process::id()));
+        std::fs::write(&file, r#"{"Path": "C:\\A", "PATH": "C:\\B"}"#).unwrap();
+        let base = load_base_env(&file);
+        let _ = std::fs::remove_file(&file);
+        let err = env::Env::default().merge_base(&base.unwrap()).unwrap_err();
+        assert_eq!(err, "variables PATH and Path differ only in case");
+    }
+
+    #[test]
+    fn detect_shell_msys() {
+        assert!(matches!(shell_for(&[("MSYSTEM", "MINGW64")]), Format::Sh));
+    }
+
+    #[test]
+    fn detect_shell_cmd_prompt() {
+        assert!(matches!(shell_for(&[("PROMPT", "$P$G")]), Format::Cmd));
+    }
+
+    #[test]
+    fn detect_shell_default_ps() {
+        assert!(matches!(shell_for(&[]), Format::Ps));
+    }
+
+    #[test]
+    fn detect_shell_from_launched_exe() {
+        // pwsh/cmd started from an MSYS2 terminal: `_` names them although MSYSTEM is inherited
+        let pwsh = [("_", r"C:\Program Files\PowerShell\7\pwsh.exe"), ("MSYSTEM", "MINGW64")];
+        assert!(matches!(shell_for(&pwsh), Format::Ps));
+        let cmd = [("_", "/c/Windows/System32/cmd.exe"), ("MSYSTEM", "MINGW64"), ("PROMPT", "$P$G")];
+        assert!(matches!(shell_for(&cmd), Format::Cmd));
+        assert!(matches!(shell_for(&[("_", "/usr/bin/fish")]), Format::Fish));
+    }
+
+    #[test]
+    fn detect_shell_ignores_other_exe() {
+        // `_` of a plain program (the last command bash ran) falls through to MSYSTEM
+        let vars = [("_", "/usr/bin/env"), ("MSYSTEM", "MINGW64")];
+        assert!(matches!(shell_for(&vars), Format::Sh));
+    }
 }
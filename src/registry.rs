@@ -4,44 +4,194 @@
 //!
 //! ## Purpose
 //! Reads installation paths from Windows registry without external dependencies.
-//! Searches across HKLM/HKCU and Wow6432Node for compatibility with 32/64-bit.
+//! Searches HKLM/HKCU in the 64-bit registry view first, then the 32-bit
+//! (Wow6432Node) view, selected explicitly with `KEY_WOW64_*` flags.
 //!
 //! ## Key Functions
-//! - `reg_val()` - Read single registry value from specific key
-//! - `reg_find()` - Search value across HKLM/HKCU and Wow6432Node variants
+//! - `reg_val()` - Read single registry value from specific key (expands REG_EXPAND_SZ)
+//! - `reg_find()` - Search value across HKLM/HKCU and both registry views
+//!
+//! - `env_key()` / `env_get()` / `env_set()` - Persisted user/machine environment (`--persist`)
+//! - `broadcast_env_change()` - `WM_SETTINGCHANGE` after persisting
+//! - `cmd_processor_key()` - cmd.exe `AutoRun` (`--install cmd --write`)
 //!
 //! ## Registry Paths Used
 //! - `Microsoft\Microsoft SDKs\Windows\v10.0` - Windows SDK location
 //! - `Microsoft\Windows Kits\Installed Roots` - UCRT location
+//! - `HKCU\Environment`, `HKLM\...\Session Manager\Environment` - Persisted environment
+//! - `HKCU\Software\Microsoft\Command Processor` - cmd.exe `AutoRun`
 //!
 //! ## Dependencies
 //! - `winreg` crate for Windows registry API
+//! - `user32` (`SendMessageTimeoutW`) for the change broadcast
 
-use winreg::enums::{HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE};
+use crate::PersistScope;
+use winreg::enums::{
+    RegType, HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE, KEY_READ, KEY_WOW64_32KEY, KEY_WOW64_64KEY, KEY_WRITE,
+};
+use winreg::types::{FromRegValue, ToRegValue};
 use winreg::RegKey;
 
-/// Read single registry value
-pub fn reg_val(root: &RegKey, path: &str, name: &str) -> Option<String> {
-    root.open_subkey(path)
-        .ok()
-        .and_then(|k| k.get_value::<String, _>(name).ok())
+/// Persisted environment keys (`--persist user|machine`)
+const USER_ENV: &str = "Environment";
+const MACHINE_ENV: &str = r"SYSTEM\CurrentControlSet\Control\Session Manager\Environment";
+
+/// cmd.exe settings of the current user (`AutoRun` runs on every cmd start without `/D`)
+const CMD_PROCESSOR: &str = r"Software\Microsoft\Command Processor";
+
+#[link(name = "user32")]
+unsafe extern "system" {
+    fn SendMessageTimeoutW(
+        hwnd: isize,
+        msg: u32,
+        wparam: usize,
+        lparam: *const u16,
+        flags: u32,
+        timeout: u32,
+        result: *mut usize,
+    ) -> isize;
+}
+
+const HWND_BROADCAST: isize = 0xffff;
+const WM_SETTINGCHANGE: u32 = 0x001A;
+const SMTO_ABORTIFHUNG: u32 = 0x0002;
+
+/// Expand `%VAR%` references like `ExpandEnvironmentStrings` (unknown names are kept as-is)
+fn expand_env_refs(s: &str) -> String {
+    expand_refs_with(s, &|name| std::env::var(name).ok())
 }
 
-/// Search registry value across HKLM/HKCU and Wow6432Node
+/// `expand_env_refs` with variables looked up through `vars`
+fn expand_refs_with(s: &str, vars: &dyn Fn(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('%') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after.find('%') {
+            Some(end) => {
+                let name = &after[..end];
+                match vars(name) {
+                    Some(val) if !name.is_empty() => out.push_str(&val),
+                    _ => {
+                        out.push('%');
+                        out.push_str(name);
+                        out.push('%');
+                    }
+                }
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Read single registry value from the given view (`KEY_WOW64_64KEY` / `KEY_WOW64_32KEY`)
+/// REG_EXPAND_SZ values are expanded
+pub fn reg_val(root: &RegKey, path: &str, name: &str, view: u32) -> Option<String> {
+    let raw = root.open_subkey_with_flags(path, KEY_READ | view).ok()?.get_raw_value(name).ok()?;
+    let val = String::from_reg_value(&raw).ok()?;
+    match raw.vtype {
+        RegType::REG_EXPAND_SZ => Some(expand_env_refs(&val)),
+        _ => Some(val),
+    }
+}
+
+/// Search registry value across HKLM/HKCU, 64-bit view first, then the 32-bit (Wow6432Node) view
+/// Views are selected with KEY_WOW64_* flags, so the result doesn't depend on the process bitness
 pub fn reg_find(path: &str, name: &str) -> Option<String> {
     let roots = [
         RegKey::predef(HKEY_LOCAL_MACHINE),
         RegKey::predef(HKEY_CURRENT_USER),
     ];
-    let prefixes = [r"SOFTWARE\Wow6432Node", r"SOFTWARE"];
+    let full_path = format!(r"SOFTWARE\{}", path);
 
     for root in &roots {
-        for prefix in &prefixes {
-            let full_path = format!(r"{}\{}", prefix, path);
-            if let Some(val) = reg_val(root, &full_path, name) {
+        for view in [KEY_WOW64_64KEY, KEY_WOW64_32KEY] {
+            if let Some(val) = reg_val(root, &full_path, name, view) {
                 return Some(val);
             }
         }
     }
     None
 }
+
+/// Open the persisted environment key of `scope` for reading and writing
+/// (`Machine` needs an elevated process)
+pub fn env_key(scope: PersistScope) -> std::io::Result<RegKey> {
+    match scope {
+        PersistScope::User => RegKey::predef(HKEY_CURRENT_USER).open_subkey_with_flags(USER_ENV, KEY_READ | KEY_WRITE),
+        PersistScope::Machine => {
+            RegKey::predef(HKEY_LOCAL_MACHINE).open_subkey_with_flags(MACHINE_ENV, KEY_READ | KEY_WRITE)
+        }
+    }
+}
+
+/// Read a persisted variable as stored (REG_EXPAND_SZ references are kept unexpanded)
+pub fn env_get(key: &RegKey, name: &str) -> Option<String> {
+    key.get_raw_value(name).ok().and_then(|raw| String::from_reg_value(&raw).ok())
+}
+
+/// Write a persisted variable, as REG_EXPAND_SZ when `expand` (PATH-like lists)
+pub fn env_set(key: &RegKey, name: &str, value: &str, expand: bool) -> std::io::Result<()> {
+    let mut raw = value.to_reg_value();
+    if expand {
+        raw.vtype = RegType::REG_EXPAND_SZ;
+    }
+    key.set_raw_value(name, &raw)
+}
+
+/// Open (creating if needed) the current user's cmd.exe settings key
+pub fn cmd_processor_key() -> std::io::Result<RegKey> {
+    RegKey::predef(HKEY_CURRENT_USER).create_subkey(CMD_PROCESSOR).map(|(key, _)| key)
+}
+
+/// Tell running programs (Explorer, new shells) that the persisted environment changed
+pub fn broadcast_env_change() {
+    let area: Vec<u16> = "Environment".encode_utf16().chain(std::iter::once(0)).collect();
+    let mut result = 0usize;
+    // SAFETY: `area` is a NUL-terminated UTF-16 string that outlives the call
+    unsafe {
+        SendMessageTimeoutW(HWND_BROADCAST, WM_SETTINGCHANGE, 0, area.as_ptr(), SMTO_ABORTIFHUNG, 5000, &mut result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(s: &str) -> String {
+        expand_refs_with(s, &|name| match name {
+            "SystemRoot" => Some(r"C:\Windows".to_string()),
+            "ProgramFiles" => Some(r"C:\Program Files".to_string()),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn expand_known_var() {
+        assert_eq!(expand(r"%SystemRoot%\System32"), r"C:\Windows\System32");
+    }
+
+    #[test]
+    fn expand_value_with_space() {
+        // Some OEM images store the SDK InstallationFolder as REG_EXPAND_SZ
+        assert_eq!(expand(r"%ProgramFiles%\Windows Kits\10\"), r"C:\Program Files\Windows Kits\10\");
+    }
+
+    #[test]
+    fn expand_unknown_var_kept() {
+        assert_eq!(expand(r"%FOO%\bin;%SystemRoot%"), r"%FOO%\bin;C:\Windows");
+    }
+
+    #[test]
+    fn expand_lone_percent_kept() {
+        assert_eq!(expand("50% done"), "50% done");
+        assert_eq!(expand("%SystemRoot"), "%SystemRoot");
+    }
+}
@@ -0,0 +1,210 @@
+//! # Self-Test Module
+//!
+//! Validates path assembly against a synthetic VS/SDK layout.
+//!
+//! ## Purpose
+//! Builds a minimal fake Visual Studio + Windows Kits tree in a temp directory,
+//! runs detection and `build_env` against it, and checks the expected paths.
+//! Independent of what is installed on the machine, and doubles as executable
+//! documentation of the directory layout vcv expects.
+//!
+//! ## Key Functions
+//! - `run()` - Create the skeleton, run all checks, print pass/fail, clean up
+//!
+//! ## Dependencies
+//! - `detect` module for `vs_from_path`/`sdk_from_root`/`ucrt_from_root`
+//! - `env` module for `build_env`
+
+use vcv_rs::detect;
+use vcv_rs::env::{build_env, Env, EnvOpts};
+use vcv_rs::{Arch, SdkSelect};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const TOOLS_VER: &str = "14.40.33807";
+const SDK_VER: &str = "10.0.22621.0";
+const REDIST_VER: &str = "14.40.33816";
+
+/// Create directories and empty marker files
+fn make_tree(root: &Path, dirs: &[PathBuf], files: &[(PathBuf, &str)]) -> std::io::Result<()> {
+    for d in dirs {
+        fs::create_dir_all(root.join(d))?;
+    }
+    for (f, content) in files {
+        let p = root.join(f);
+        if let Some(parent) = p.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(p, content)?;
+    }
+    Ok(())
+}
+
+/// Build the synthetic layout: `<root>\VS` (VS install) and `<root>\Kits\10` (SDK + UCRT)
+fn make_skeleton(root: &Path) -> std::io::Result<()> {
+    let tools = PathBuf::from("VS").join("VC").join("Tools").join("MSVC").join(TOOLS_VER);
+    let kits = PathBuf::from("Kits").join("10");
+
+    let dirs = [
+        tools.join("include"),
+        tools.join("lib").join("x64"),
+        tools.join("lib").join("x86"),
+        tools.join("lib").join("arm64"),
+        tools.join("ATLMFC").join("include"),
+        tools.join("ATLMFC").join("lib").join("x64"),
+        kits.join("include").join(SDK_VER).join("shared"),
+        kits.join("include").join(SDK_VER).join("winrt"),
+        kits.join("include").join(SDK_VER).join("cppwinrt"),
+        kits.join("include").join(SDK_VER).join("ucrt"),
+        kits.join("Lib").join(SDK_VER).join("um").join("x64"),
+        kits.join("Lib").join(SDK_VER).join("um").join("x86"),
+        kits.join("Lib").join(SDK_VER).join("um").join("arm64"),
+        kits.join("Lib").join(SDK_VER).join("ucrt").join("x86"),
+        kits.join("Lib").join(SDK_VER).join("ucrt").join("arm64"),
+        kits.join("bin").join(SDK_VER).join("x64"),
+        kits.join("bin").join(SDK_VER).join("x86"),
+        kits.join("bin").join(SDK_VER).join("arm64"),
+        kits.join("bin").join("x64"),
+        kits.join("bin").join("arm64"),
+        kits.join("UnionMetadata").join(SDK_VER),
+        kits.join("References").join(SDK_VER),
+        PathBuf::from("VS").join("VC").join("Redist").join("MSVC").join(REDIST_VER),
+    ];
+    let files = [
+        (
+            PathBuf::from("VS").join("VC").join("Auxiliary").join("Build")
+                .join("Microsoft.VCToolsVersion.default.txt"),
+            TOOLS_VER,
+        ),
+        (
+            PathBuf::from("VS").join("VC").join("Auxiliary").join("Build")
+                .join("Microsoft.VCRedistVersion.default.txt"),
+            REDIST_VER,
+        ),
+        (tools.join("bin").join("Hostx64").join("x64").join("cl.exe"), ""),
+        (tools.join("bin").join("Hostx64").join("x86").join("cl.exe"), ""),
+        (tools.join("bin").join("Hostarm64").join("arm64").join("cl.exe"), ""),
+        (tools.join("bin").join("Hostarm64").join("x64").join("cl.exe"), ""),
+        (kits.join("include").join(SDK_VER).join("um").join("winsdkver.h"), ""),
+        (kits.join("Lib").join(SDK_VER).join("ucrt").join("x64").join("ucrt.lib"), ""),
+    ];
+    make_tree(root, &dirs, &files)?;
+    make_markerless_vs(root)
+}
+
+/// VS install without the `Microsoft.VCToolsVersion.*.txt` markers under `<root>\NoMarker`:
+/// the newest toolset lacks cl.exe, so the one before it must be picked
+fn make_markerless_vs(root: &Path) -> std::io::Result<()> {
+    let msvc = PathBuf::from("NoMarker").join("VC").join("Tools").join("MSVC");
+    let dirs = [msvc.join("14.99.0")];
+    let files = [
+        (msvc.join(TOOLS_VER).join("bin").join("Hostx64").join("x64").join("cl.exe"), ""),
+        (msvc.join("14.29.30133").join("bin").join("Hostx64").join("x64").join("cl.exe"), ""),
+    ];
+    make_tree(root, &dirs, &files)
+}
+
+/// Single named check
+struct Check {
+    name: String,
+    ok: bool,
+}
+
+fn check_has(checks: &mut Vec<Check>, var: &str, list: &[PathBuf], expected: PathBuf) {
+    checks.push(Check {
+        name: format!("{} contains {}", var, expected.display()),
+        ok: list.contains(&expected),
+    });
+}
+
+/// Checks for one host/target pair against the skeleton
+fn check_env(checks: &mut Vec<Check>, env: &Env, root: &Path, host: Arch, target: Arch) {
+    let tools = root.join("VS").join("VC").join("Tools").join("MSVC").join(TOOLS_VER);
+    let kits = root.join("Kits").join("10");
+    let tgt = target.as_str();
+
+    check_has(checks, "PATH", &env.path, tools.join("bin").join(host.host_dir()).join(tgt));
+    if host != target {
+        check_has(checks, "PATH", &env.path, tools.join("bin").join(host.host_dir()).join(host.as_str()));
+    }
+    check_has(checks, "INCLUDE", &env.include, tools.join("include"));
+    check_has(checks, "INCLUDE", &env.include, kits.join("include").join(SDK_VER).join("um"));
+    check_has(checks, "INCLUDE", &env.include, kits.join("include").join(SDK_VER).join("ucrt"));
+    check_has(checks, "LIB", &env.lib, tools.join("lib").join(tgt));
+    check_has(checks, "LIB", &env.lib, kits.join("Lib").join(SDK_VER).join("um").join(tgt));
+    check_has(checks, "LIB", &env.lib, kits.join("Lib").join(SDK_VER).join("ucrt").join(tgt));
+    check_has(checks, "LIBPATH", &env.libpath, kits.join("UnionMetadata").join(SDK_VER));
+
+    checks.push(Check {
+        name: format!("target cl.exe first on PATH ({} -> {})", host.as_str(), tgt),
+        ok: env.cl_path() == Some(tools.join("bin").join(host.host_dir()).join(tgt).join("cl.exe")),
+    });
+    checks.push(Check {
+        name: format!("Platform = {}", tgt),
+        ok: env.vars.get("Platform").map(String::as_str) == Some(tgt),
+    });
+}
+
+/// Run all checks against the skeleton rooted at `root`
+fn run_checks(root: &Path) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    let vs = detect::vs_from_path(&root.join("VS"), None);
+    let sdk = detect::sdk_from_root(&root.join("Kits").join("10"), SdkSelect::Newest, None);
+    let ucrt = detect::ucrt_from_root(&root.join("Kits").join("10"), None);
+
+    checks.push(Check {
+        name: format!("VS toolset {} detected", TOOLS_VER),
+        ok: vs.as_ref().is_some_and(|v| v.tools_ver == TOOLS_VER),
+    });
+    checks.push(Check {
+        name: format!("SDK {} detected", SDK_VER),
+        ok: sdk.as_ref().is_some_and(|s| s.version == SDK_VER),
+    });
+    checks.push(Check {
+        name: format!("UCRT {} detected", SDK_VER),
+        ok: ucrt.as_ref().is_some_and(|u| u.version == SDK_VER),
+    });
+
+    checks.push(Check {
+        name: format!("toolset {} picked without default markers", TOOLS_VER),
+        ok: detect::vs_from_path(&root.join("NoMarker"), None).is_some_and(|v| v.tools_ver == TOOLS_VER),
+    });
+
+    if let Some(vs) = vs {
+        let pairs = [
+            (Arch::X64, Arch::X64),
+            (Arch::X64, Arch::X86),
+            (Arch::Arm64, Arch::Arm64),
+            (Arch::Arm64, Arch::X64),
+        ];
+        for (host, target) in pairs {
+            let env = build_env(&vs, sdk.as_ref(), ucrt.as_ref(), host, target, &EnvOpts::default());
+            check_env(&mut checks, &env, root, host, target);
+        }
+    }
+
+    checks
+}
+
+/// Run the self-test, print results to stdout; returns true when all checks pass
+pub fn run() -> bool {
+    let root = std::env::temp_dir().join(format!("vcv-selftest-{}", std::process::id()));
+
+    if let Err(e) = make_skeleton(&root) {
+        println!("FAIL  create skeleton in {}: {}", root.display(), e);
+        let _ = fs::remove_dir_all(&root);
+        return false;
+    }
+
+    let checks = run_checks(&root);
+    let _ = fs::remove_dir_all(&root);
+
+    for c in &checks {
+        println!("{}  {}", if c.ok { "PASS" } else { "FAIL" }, c.name);
+    }
+
+    let failed = checks.iter().filter(|c| !c.ok).count();
+    println!("{} passed, {} failed", checks.len() - failed, failed);
+    failed == 0
+}
@@ -0,0 +1,47 @@
+//! # Environment Snapshot Module
+//!
+//! Saves and loads a fully resolved environment for reproducible replay.
+//!
+//! ## Purpose
+//! Captures the detected VS/SDK/UCRT (with exact pinned versions) together with
+//! the assembled environment, so the same environment can be emitted later
+//! without re-running detection - even after a VS update changes the defaults.
+//!
+//! ## Key Functions
+//! - `Snapshot::save()` - Write snapshot as pretty JSON
+//! - `Snapshot::load()` - Read snapshot back
+//!
+//! ## Dependencies
+//! - `serde_json` for (de)serialization
+
+use vcv_rs::detect::{SdkInfo, VsInfo};
+use vcv_rs::env::Env;
+use vcv_rs::Arch;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Resolved detection results plus assembled environment
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub host: Arch,
+    pub target: Arch,
+    pub vs: VsInfo,
+    pub sdk: Option<SdkInfo>,
+    pub ucrt: Option<SdkInfo>,
+    pub env: Env,
+}
+
+impl Snapshot {
+    /// Write snapshot to a JSON file
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, json).map_err(|e| format!("Cannot write {}: {}", path.display(), e))
+    }
+
+    /// Read snapshot from a JSON file
+    pub fn load(path: &Path) -> Result<Snapshot, String> {
+        let data = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        serde_json::from_str(&data).map_err(|e| format!("Invalid snapshot {}: {}", path.display(), e))
+    }
+}
@@ -0,0 +1,114 @@
+//! # Verify Module
+//!
+//! Compares vcv's environment with what the real vcvarsall.bat produces.
+//!
+//! ## Purpose
+//! Runs `VC\Auxiliary\Build\vcvarsall.bat` for the same host/target in a child
+//! cmd.exe, captures its `set` output and diffs PATH/INCLUDE/LIB/LIBPATH against
+//! the assembled `Env`. Entries the shell already had are ignored, so only what
+//! each side adds is compared. Opt-in (`--verify`) because it runs the slow batch file.
+//!
+//! ## Key Functions
+//! - `run(vs, host, target, env, quiet)` - Print missing/extra entries, return the exit code
+//!
+//! ## Dependencies
+//! - `env::Env` for the assembled path lists
+
+use std::collections::HashSet;
+use std::process::Command;
+use std::time::Instant;
+use vcv_rs::detect::VsInfo;
+use vcv_rs::env::Env;
+use vcv_rs::Arch;
+
+/// vcvarsall.bat argument: `x64`, `x64_arm64`, `arm64_x64`, ...
+fn vcvarsall_arg(host: Arch, target: Arch) -> String {
+    if host.bin_dir() == target.bin_dir() {
+        host.as_str().to_string()
+    } else {
+        format!("{}_{}", host.as_str(), target.bin_dir())
+    }
+}
+
+/// Comparison key: case-insensitive, without trailing separators (as on Windows)
+fn key(p: &str) -> String {
+    p.trim_end_matches(['\\', '/']).to_lowercase()
+}
+
+/// Variables after running vcvarsall.bat, as (name, value) from `set`
+fn run_vcvarsall(vs: &VsInfo, host: Arch, target: Arch) -> Result<Vec<(String, String)>, String> {
+    let bat = vs.vc.join("Auxiliary").join("Build").join("vcvarsall.bat");
+    if !bat.exists() {
+        return Err(format!("{} not found", bat.display()));
+    }
+    let bat_str = bat.display().to_string();
+    let arg = vcvarsall_arg(host, target);
+    let output = Command::new("cmd")
+        .args(["/d", "/c", "call", &bat_str, &arg, ">nul", "&&", "set"])
+        .output()
+        .map_err(|e| format!("cannot run cmd.exe: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("{} {} failed ({})", bat.display(), arg, output.status));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|l| l.split_once('='))
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect())
+}
+
+/// Run vcvarsall.bat and print, per path list, the entries vcv is missing and the extra ones
+/// Returns 0 when both agree, 1 on differences, 2 when vcvarsall.bat couldn't be run
+pub fn run(vs: &VsInfo, host: Arch, target: Arch, env: &Env, quiet: bool) -> i32 {
+    let start = Instant::now();
+    let vars = match run_vcvarsall(vs, host, target) {
+        Ok(vars) => vars,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return 2;
+        }
+    };
+    if !quiet {
+        let ms = start.elapsed().as_secs_f64() * 1000.0;
+        eprintln!("# vcvarsall.bat {} took {:.0} ms", vcvarsall_arg(host, target), ms);
+    }
+
+    let mut differences = 0;
+    for (name, list) in env.lists() {
+        // Entries inherited from this shell appear on both sides: leave them out
+        let current = std::env::var(name).unwrap_or_default();
+        let inherited: HashSet<String> = current.split(';').map(key).collect();
+        let theirs: Vec<&str> = vars
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.split(';').filter(|e| !e.is_empty() && !inherited.contains(&key(e))).collect())
+            .unwrap_or_default();
+        let ours: Vec<String> = list
+            .iter()
+            .map(|p| p.display().to_string())
+            .filter(|e| !inherited.contains(&key(e)))
+            .collect();
+
+        let their_keys: HashSet<String> = theirs.iter().map(|e| key(e)).collect();
+        let our_keys: HashSet<String> = ours.iter().map(|e| key(e)).collect();
+        let missing: Vec<&str> = theirs.iter().copied().filter(|e| !our_keys.contains(&key(e))).collect();
+        let extra: Vec<&String> = ours.iter().filter(|e| !their_keys.contains(&key(e))).collect();
+
+        println!("{}:", name);
+        if missing.is_empty() && extra.is_empty() {
+            println!("  (same)");
+        }
+        for e in &missing {
+            println!("  - {}", e);
+        }
+        for e in &extra {
+            println!("  + {}", e);
+        }
+        differences += missing.len() + extra.len();
+    }
+
+    if !quiet {
+        eprintln!("# - only set by vcvarsall.bat, + only set by vcv ({} differences)", differences);
+    }
+    if differences == 0 { 0 } else { 1 }
+}